@@ -43,6 +43,7 @@ fi
 # Kill existing Python daemons and old helper
 pkill -f 'alternating_layouts.py' 2>/dev/null || true
 pkill -f 'workspace-names.py' 2>/dev/null || true
+pkill -f 'sway_autotiling.py' 2>/dev/null || true
 pkill -x 'i3-helper' 2>/dev/null || true
 sleep 0.2
 
@@ -52,11 +53,13 @@ disown
 
 echo -e "${GREEN}${BOLD}✓ i3-helper started (pid=$!)${RESET}"
 echo ""
-echo "i3 config already updated. Reload i3 with \$mod+Shift+r"
+echo "i3 and sway configs already updated. Reload with \$mod+Shift+r"
 echo ""
 echo "Keybindings:"
 echo "  \$mod+'         → goto mark"
 echo "  \$mod+Shift+'   → set mark"
 echo "  \$mod+Ctrl+'    → swap with mark"
-echo "  \$mod+t         → cycle tiling mode (alt/vert/horiz)"
+echo "  \$mod+t         → cycle the focused workspace's tiling mode"
+echo "                   (alternating → vertical → horizontal → master → spiral)"
+echo "  \$mod+Shift+t   → mode selector: letter = this workspace, Shift+letter = default"
 echo "  \$mod+Shift+u   → unmark focused"
\ No newline at end of file
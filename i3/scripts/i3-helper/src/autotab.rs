@@ -0,0 +1,169 @@
+//! Automatic tabbed/stacked fallback for crowded split containers.
+//!
+//! When a split container reaches the workspace's threshold of tiling
+//! children it is switched to tabbed (or stacked); when the count drops
+//! below the threshold again it goes back to its original split. Only
+//! containers switched by us are ever switched back, so a tabbed layout
+//! chosen by hand is left alone; likewise a container the user turned back
+//! into a split stays split until it drops below the threshold.
+
+use crate::config::{AutoTab, TabLayout};
+use crate::{collect_workspaces, ws_key};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use swayipc::{Connection, Node, NodeLayout, NodeType};
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Switched by us; holds the split layout to restore.
+    Switched(NodeLayout),
+    /// User reverted our switch; leave it alone while it stays crowded.
+    Declined,
+}
+
+static STATES: Mutex<BTreeMap<i64, State>> = Mutex::new(BTreeMap::new());
+
+#[inline]
+fn split_cmd(layout: NodeLayout) -> &'static str {
+    match layout {
+        NodeLayout::SplitV => "splitv",
+        _ => "splith",
+    }
+}
+
+/// Switch/restore containers across all workspaces to match the thresholds.
+pub fn apply(cmd: &mut Connection, tree: &Node, cfg: &AutoTab) -> Result<()> {
+    let mut states = STATES.lock().unwrap_or_else(|e| e.into_inner());
+    let cmds = plan(tree, cfg, &mut states);
+    drop(states);
+
+    if !cmds.is_empty() {
+        cmd.run_command(cmds.join("; "))?;
+    }
+    Ok(())
+}
+
+/// Commands bringing `tree` in line with `cfg`, updating `states`.
+fn plan(tree: &Node, cfg: &AutoTab, states: &mut BTreeMap<i64, State>) -> Vec<String> {
+    let mut ws_buf = Vec::with_capacity(10);
+    collect_workspaces(tree, &mut ws_buf);
+
+    let mut seen = Vec::new();
+    let mut cmds = Vec::new();
+    for ws in ws_buf {
+        let (threshold, layout) = cfg.for_workspace(&ws_key(ws));
+        visit(ws, threshold, layout, states, &mut seen, &mut cmds);
+    }
+    // Closed, restored, or no longer crowded
+    states.retain(|id, _| seen.contains(id));
+    cmds
+}
+
+fn visit(
+    con: &Node,
+    threshold: usize,
+    layout: TabLayout,
+    states: &mut BTreeMap<i64, State>,
+    seen: &mut Vec<i64>,
+    cmds: &mut Vec<String>,
+) {
+    // i3 makes a non-empty workspace tabbed/stacked by wrapping all of its
+    // children in a new container; carry our state over to that wrapper.
+    if con.node_type == NodeType::Workspace && con.nodes.len() == 1 {
+        if let Some(state) = states.remove(&con.id) {
+            let wrapper = &con.nodes[0];
+            if matches!(wrapper.layout, NodeLayout::Tabbed | NodeLayout::Stacked) {
+                states.insert(wrapper.id, state);
+            }
+        }
+    }
+
+    // `layout` on a child changes its parent's layout
+    let first_child = match con.nodes.first() {
+        Some(c) => c.id,
+        None => return,
+    };
+    let n = con.nodes.len();
+
+    let crowded = threshold > 0 && n >= threshold;
+
+    match (con.layout, states.get(&con.id).copied()) {
+        (NodeLayout::SplitH | NodeLayout::SplitV, None) if crowded => {
+            cmds.push(format!("[con_id={first_child}] layout {}", layout.as_cmd()));
+            states.insert(con.id, State::Switched(con.layout));
+            seen.push(con.id);
+        }
+        (NodeLayout::SplitH | NodeLayout::SplitV, Some(_)) if crowded => {
+            states.insert(con.id, State::Declined);
+            seen.push(con.id);
+        }
+        (NodeLayout::Tabbed | NodeLayout::Stacked, Some(State::Switched(orig))) => {
+            if crowded {
+                seen.push(con.id);
+            } else {
+                cmds.push(format!("[con_id={first_child}] layout {}", split_cmd(orig)));
+            }
+        }
+        _ => {}
+    }
+
+    for child in &con.nodes {
+        visit(child, threshold, layout, states, seen, cmds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AutoTabWorkspace;
+    use crate::testutil::{leaf, split, workspace};
+
+    fn column(layout: &str, n: i64) -> Node {
+        let leaves = (1..=n).map(|id| leaf(id, 0, 0, 100, 100)).collect();
+        workspace("splith", vec![split(layout, leaves)])
+    }
+
+    #[test]
+    fn switches_crowded_splits_and_restores_them() {
+        let cfg = AutoTab { threshold: 3, ..AutoTab::default() };
+        let mut states = BTreeMap::new();
+
+        assert!(plan(&column("splitv", 2), &cfg, &mut states).is_empty());
+        assert_eq!(plan(&column("splitv", 3), &cfg, &mut states), ["[con_id=1] layout tabbed"]);
+        // Still crowded: nothing to do
+        assert!(plan(&column("tabbed", 4), &cfg, &mut states).is_empty());
+        // Below the threshold: back to the original split
+        assert_eq!(plan(&column("tabbed", 2), &cfg, &mut states), ["[con_id=1] layout splitv"]);
+        assert!(states.is_empty());
+    }
+
+    #[test]
+    fn leaves_user_choices_alone() {
+        let cfg = AutoTab { threshold: 3, ..AutoTab::default() };
+        let mut states = BTreeMap::new();
+
+        // Tabbed by hand: never switched back
+        assert!(plan(&column("tabbed", 2), &cfg, &mut states).is_empty());
+
+        // Reverted by hand: stays split while crowded, re-armed once it isn't
+        plan(&column("splith", 3), &cfg, &mut states);
+        assert!(plan(&column("splith", 4), &cfg, &mut states).is_empty());
+        assert!(plan(&column("splith", 2), &cfg, &mut states).is_empty());
+        assert_eq!(plan(&column("splith", 3), &cfg, &mut states).len(), 1);
+    }
+
+    #[test]
+    fn per_workspace_overrides() {
+        let mut cfg = AutoTab { threshold: 3, ..AutoTab::default() };
+        cfg.workspaces.insert(
+            "1".to_string(),
+            AutoTabWorkspace { threshold: Some(2), layout: Some(TabLayout::Stacked) },
+        );
+        let mut states = BTreeMap::new();
+        assert_eq!(plan(&column("splitv", 2), &cfg, &mut states), ["[con_id=1] layout stacking"]);
+
+        cfg.workspaces.insert("1".to_string(), AutoTabWorkspace { threshold: Some(0), layout: None });
+        assert!(plan(&column("splitv", 9), &cfg, &mut BTreeMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,726 @@
+//! User configuration: `$XDG_CONFIG_HOME/i3-helper/config.toml`.
+//!
+//! Every section is optional and a missing file means built-in defaults.
+//! See `config.example.toml` next to `Cargo.toml` for a commented example.
+//!
+//!   [icons]
+//!   size = "x-large"                 # span size for entries without one
+//!   remove = ["st", "virtualbox"]    # drop built-in entries
+//!
+//!   [icons.apps]
+//!   obsidian = "󰠮"                            # add: glyph at default size
+//!   scratchpad = { icon = "󰘳", size = "large" } # add/override with size
+//!   kitty = { size = "large" }                 # resize a built-in glyph
+//!
+//!   [names]
+//!   mode = "all"        # "first" (first window only) | "all" (every app)
+//!   count = true        # "all": append ×N for apps with several windows
+//!   max_icons = 4       # "all": show at most N icons, then +K (0 = no cap)
+//!   restore_on_exit = false  # bare numbers again when the daemon stops
+//!
+//!   [master]            # master–stack tiling mode
+//!   fraction = 0.55     # master column width (0.1–0.9)
+//!   count = 1           # windows in the master area
+//!
+//!   [spiral]            # spiral tiling mode
+//!   rotate = true       # spiral through quadrants; false = dwindle
+//!
+//!   [autotab]           # tab crowded split containers; see `autotab`
+//!   threshold = 5       # children that trigger the switch (0 = off)
+//!   layout = "tabbed"   # or "stacked"
+//!   [autotab.workspaces]
+//!   "3" = { threshold = 3, layout = "stacked" }
+//!
+//!   [workspaces]
+//!   dynamic = false     # keep numbers contiguous per output; see `dynamic`
+//!
+//!   [swallow]           # window takes its terminal's tile; see `swallow`
+//!   enabled = true
+//!   terminals = ["kitty", "alacritty"]
+//!   allow = []          # child classes to swallow (empty = any)
+//!   deny = ["firefox"]  # never swallowed
+//!
+//!   [switch]            # `i3-helper switch` menu, run by `sh -c`
+//!   command = "rofi -dmenu -i -p window"
+//!
+//!   [session]           # crash recovery snapshots; see `session`
+//!   interval = 30       # seconds between writes (0 = off)
+//!
+//!   [marks]             # `i3-helper mark` menu, run by `sh -c`
+//!   command = "rofi -dmenu -p mark"
+//!   [[marks.auto]]      # mark windows on sight (a/f/g/t built in); see `marks`
+//!   mark = "c"
+//!   class = "^code$"
+//!
+//!   [scratchpads.notes] # `i3-helper scratch notes`; see `scratch`
+//!   command = "obsidian"
+//!   class = "^obsidian$"
+//!   width = 70          # percent of the output
+//!   height = 80
+//!   position = "right"  # center | top | bottom | left | right
+//!   dismiss_on_blur = true  # hide when focus moves away...
+//!   grace = 300         # ...for this many milliseconds
+//!
+//!   [[rules]]           # icon by window properties; see `rules`
+//!   class = "^kitty$"
+//!   title = "n?vim"
+//!   icon = "nvim"
+//!
+//! A file that fails to parse is reported (stderr + notification) and the
+//! daemon carries on with the built-in map instead of exiting.
+//!
+//! The file is watched with inotify and re-applied live: `get()` always
+//! returns the latest good config, and a broken edit keeps the previous one.
+
+use crate::marks::{self, AutoMark, RawAutoMark};
+use crate::rules::{self, RawRule, Rule};
+use crate::scratch::{self, RawScratchpad, Scratchpad};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::FromRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command as Cmd;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
+use std::{env, fs, io, thread};
+
+/// Window class (lowercased) → rendered Pango `<span>` for i3bar.
+pub type IconMap = HashMap<String, String>;
+
+const DEFAULT_SIZE: &str = "x-large";
+
+// ── Built-in Icon Map ─────────────────────────────────────────
+// (class, glyph, span size). Rendered as `<span size='…'>glyph </span>`,
+// matching the markup of the original Python version.
+
+const BUILTIN_ICONS: &[(&str, &str, &str)] = &[
+    // Browsers
+    ("firefox",             "\u{E658}",   "x-large"),
+    ("google-chrome",       "\u{F268}",   "x-large"),
+    // Terminals
+    ("alacritty",           "\u{EBCA}",   "x-large"),
+    ("kitty",               "\u{EBCA}",   "x-large"),
+    ("st",                  "\u{EBCA}",   "x-large"),
+    // Editors
+    ("code",                "\u{F0A1E}",  "x-large"),
+    ("code-oss",            "\u{F0A1E}",  "x-large"),
+    ("antigravity",         "\u{E887}",   "x-large"),
+    ("nvim",                "\u{F36F}",   "x-large"),
+    ("vim",                 "\u{E7C5}",   "x-large"),
+    ("neovide",             "\u{F36F}",   "x-large"),
+    ("emacs",               "\u{E632}",   "x-large"),
+    // Development
+    ("jetbrains-idea",      "\u{E7B5}",   "x-large"),
+    ("jetbrains-clion",     "\u{E61D}",   "x-large"),
+    ("jetbrains-pycharm",   "\u{E73C}",   "x-large"),
+    ("jetbrains-webstorm",  "\u{F06E6}",  "x-large"),
+    ("jetbrains-rider",     "\u{F01A7}",  "x-large"),
+    ("jetbrains-goland",    "\u{E627}",   "x-large"),
+    ("jetbrains-datagrip",  "\u{F1C0}",   "x-large"),
+    ("jetbrains-rubymine",  "\u{E739}",   "x-large"),
+    ("jetbrains-phpstorm",  "\u{E608}",   "x-large"),
+    ("postman",             "\u{F06EE}",  "x-large"),
+    ("docker",              "\u{F21F}",   "x-large"),
+    ("virt-manager",        "\u{F0894}",  "x-large"),
+    ("gnome-boxes",         "\u{F0894}",  "x-large"),
+    ("virtualbox",          "\u{F0894}",  "x-large"),
+    // Communication
+    ("telegramdesktop",     "\u{F2C6}",   "x-large"),
+    ("telegram",            "\u{F2C6}",   "x-large"),
+    ("evolution",           "\u{F01F0}",  "x-large"),
+    // Media
+    ("pavucontrol",         "\u{F057E}",  "x-large"),
+    ("vlc",                 "\u{F057C}",  "x-large"),
+    ("mpv",                 "\u{F36E}",   "x-large"),
+    ("obs",                 "\u{F044B}",  "x-large"),
+    ("obs-studio",          "\u{F044B}",  "x-large"),
+    ("gimp",                "\u{F338}",   "x-large"),
+    ("inkscape",            "\u{F33B}",   "x-large"),
+    ("steam",               "\u{F1B6}",   "x-large"),
+    // System & Utilities
+    ("thunar",              "\u{F07B}",   "x-large"),
+    ("yazi",                "\u{F07B}",   "x-large"),
+    ("htop",                "\u{F04C5}",  "x-large"),
+    ("btop",                "\u{F04C5}",  "x-large"),
+    ("gparted",             "\u{F02CA}",  "x-large"),
+    ("clock",               "\u{F017}",   "x-large"),
+    ("peaclock",            "\u{F017}",   "x-large"),
+    ("calc",                "\u{F1EC}",   "x-large"),
+    ("calculator",          "\u{F1EC}",   "x-large"),
+    ("galculator",          "\u{F1EC}",   "x-large"),
+    ("zathura",             "\u{F1C1}",   "x-large"),
+    // Custom
+    ("scratchpad",          "\u{F0633}",  "large"),
+    ("main-tmux",           "\u{EBC8}",   "x-large"),
+    ("gemini",              "\u{F06A9}",  "x-large"),
+    ("gemini-sc",           "\u{F06A9}",  "x-large"),
+    ("task",                "\u{F0AE}",   "x-large"),
+    ("tasks",               "\u{F0AE}",   "x-large"),
+    ("todo",                "\u{F0AE}",   "x-large"),
+];
+
+/// Glyphs are text inside Pango markup: a bare `&` or `<` makes i3 drop
+/// the whole workspace name.
+fn escape(glyph: &str) -> String {
+    let mut out = String::with_capacity(glyph.len());
+    for c in glyph.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[inline]
+fn span(glyph: &str, size: &str) -> String {
+    format!("<span size='{}'>{} </span>", size, escape(glyph))
+}
+
+// ── Loaded Configuration ──────────────────────────────────────
+
+#[derive(Debug)]
+pub struct Config {
+    pub icons: IconMap,
+    pub names: Names,
+    pub master: Master,
+    pub spiral: Spiral,
+    pub autotab: AutoTab,
+    pub workspaces: Workspaces,
+    pub swallow: Swallow,
+    pub switch: Switch,
+    pub session: Session,
+    pub marks: Marks,
+    /// By name; the built-ins (`term`, `music`, ...) unless replaced.
+    pub scratchpads: HashMap<String, Scratchpad>,
+    /// Sorted by priority; first match wins.
+    pub rules: Vec<Rule>,
+}
+
+impl Config {
+    /// The built-in defaults, compiled like an empty config file.
+    pub fn builtin() -> Result<Config> {
+        RawConfig::default().into_config()
+    }
+
+    /// Last resort when even the built-ins don't compile: icons and plain
+    /// settings only, no auto marks or scratchpads.
+    fn minimal() -> Config {
+        let icons = BUILTIN_ICONS
+            .iter()
+            .map(|&(class, glyph, size)| (class.to_string(), span(glyph, size)))
+            .collect();
+        Config {
+            icons,
+            names: Names::default(),
+            master: Master::default(),
+            spiral: Spiral::default(),
+            autotab: AutoTab::default(),
+            workspaces: Workspaces::default(),
+            swallow: Swallow::default(),
+            switch: Switch::default(),
+            session: Session::default(),
+            marks: Marks { command: MARKS_COMMAND.into(), auto: Vec::new() },
+            scratchpads: HashMap::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// How workspace labels are built from the windows on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameMode {
+    /// Icon of the first window in tree order.
+    First,
+    /// Every distinct app, deduplicated, in tree order.
+    All,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Names {
+    pub mode: NameMode,
+    pub count: bool,
+    pub max_icons: usize,
+    /// Rename workspaces back to their bare numbers on SIGTERM/SIGINT.
+    pub restore_on_exit: bool,
+}
+
+impl Default for Names {
+    fn default() -> Self {
+        Names {
+            mode: NameMode::First,
+            count: true,
+            max_icons: 4,
+            restore_on_exit: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Master {
+    pub fraction: f64,
+    pub count: usize,
+}
+
+impl Default for Master {
+    fn default() -> Self {
+        Master { fraction: 0.55, count: 1 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Spiral {
+    pub rotate: bool,
+}
+
+impl Default for Spiral {
+    fn default() -> Self {
+        Spiral { rotate: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TabLayout {
+    #[default]
+    Tabbed,
+    Stacked,
+}
+
+impl TabLayout {
+    #[inline]
+    pub fn as_cmd(self) -> &'static str {
+        match self {
+            TabLayout::Tabbed => "tabbed",
+            TabLayout::Stacked => "stacking",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoTab {
+    /// Children at which a split container is switched (0 = off).
+    pub threshold: usize,
+    pub layout: TabLayout,
+    /// Overrides keyed by workspace number (or name).
+    pub workspaces: HashMap<String, AutoTabWorkspace>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoTabWorkspace {
+    pub threshold: Option<usize>,
+    pub layout: Option<TabLayout>,
+}
+
+impl AutoTab {
+    /// Effective (threshold, layout) for the workspace with `ws_key`.
+    pub fn for_workspace(&self, ws_key: &str) -> (usize, TabLayout) {
+        let ws = self.workspaces.get(ws_key);
+        (
+            ws.and_then(|w| w.threshold).unwrap_or(self.threshold),
+            ws.and_then(|w| w.layout).unwrap_or(self.layout),
+        )
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Workspaces {
+    /// Renumber non-empty workspaces to stay contiguous per output.
+    pub dynamic: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Swallow {
+    pub enabled: bool,
+    /// Classes whose windows can be swallowed by their children.
+    pub terminals: Vec<String>,
+    /// Child classes that are swallowed (empty = any).
+    pub allow: Vec<String>,
+    /// Child classes that never are.
+    pub deny: Vec<String>,
+}
+
+impl Default for Swallow {
+    fn default() -> Self {
+        Swallow {
+            enabled: false,
+            terminals: ["kitty", "alacritty", "st", "foot"].map(String::from).to_vec(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Switch {
+    /// dmenu-compatible menu: lines on stdin, the pick on stdout.
+    pub command: String,
+}
+
+impl Default for Switch {
+    fn default() -> Self {
+        Switch {
+            command: ". ~/.config/i3/scripts/dmenu-theme.sh && dmenu_themed -l 15 -p '  Window:'".into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Session {
+    /// Seconds between snapshot writes (0 = no snapshots).
+    pub interval: u64,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session { interval: 30 }
+    }
+}
+
+#[derive(Debug)]
+pub struct Marks {
+    /// dmenu-compatible menu for picking or typing a mark.
+    pub command: String,
+    /// Marks given to matching windows automatically.
+    pub auto: Vec<AutoMark>,
+}
+
+/// Default `marks.command`.
+const MARKS_COMMAND: &str = ". ~/.config/i3/scripts/dmenu-theme.sh && dmenu_themed -p '  Mark:'";
+
+// ── File Format ───────────────────────────────────────────────
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    icons: RawIcons,
+    names: Names,
+    master: Master,
+    spiral: Spiral,
+    autotab: AutoTab,
+    workspaces: Workspaces,
+    swallow: Swallow,
+    switch: Switch,
+    session: Session,
+    marks: RawMarks,
+    scratchpads: HashMap<String, RawScratchpad>,
+    rules: Vec<RawRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawMarks {
+    command: String,
+    auto: Vec<RawAutoMark>,
+}
+
+impl Default for RawMarks {
+    fn default() -> Self {
+        RawMarks { command: MARKS_COMMAND.into(), auto: Vec::new() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawIcons {
+    size: String,
+    remove: Vec<String>,
+    apps: HashMap<String, RawIcon>,
+}
+
+impl Default for RawIcons {
+    fn default() -> Self {
+        RawIcons {
+            size: DEFAULT_SIZE.into(),
+            remove: Vec::new(),
+            apps: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawIcon {
+    Glyph(String),
+    Styled {
+        icon: Option<String>,
+        size: Option<String>,
+    },
+}
+
+/// Size ends up inside `size='…'`; anything that could break out of the
+/// attribute would corrupt every workspace label at once.
+fn check_size(class: &str, size: &str) -> Result<()> {
+    if size.is_empty() || size.contains(['\'', '"', '<', '>']) {
+        bail!("icons.apps.{class}: invalid span size '{size}'");
+    }
+    Ok(())
+}
+
+impl RawConfig {
+    fn into_config(self) -> Result<Config> {
+        let mut builtin: HashMap<&str, (&str, &str)> = BUILTIN_ICONS
+            .iter()
+            .map(|&(class, glyph, size)| (class, (glyph, size)))
+            .collect();
+
+        let RawIcons { size: default_size, remove, apps } = self.icons;
+        check_size("size", &default_size)?;
+        for class in &remove {
+            builtin.remove(class.to_lowercase().as_str());
+        }
+
+        let mut icons: IconMap = builtin
+            .iter()
+            .map(|(&class, &(glyph, size))| (class.to_string(), span(glyph, size)))
+            .collect();
+
+        for (class, entry) in apps {
+            let class = class.to_lowercase();
+            let (glyph, size) = match entry {
+                RawIcon::Glyph(glyph) => (glyph, default_size.clone()),
+                RawIcon::Styled { icon, size } => {
+                    let base = BUILTIN_ICONS.iter().find(|e| e.0 == class);
+                    let glyph = match (icon, base) {
+                        (Some(g), _) => g,
+                        (None, Some(&(_, g, _))) => g.to_string(),
+                        (None, None) => {
+                            bail!("icons.apps.{class}: 'icon' is required for apps without a built-in glyph")
+                        }
+                    };
+                    let size = size
+                        .or_else(|| base.map(|&(_, _, s)| s.to_string()))
+                        .unwrap_or_else(|| default_size.clone());
+                    (glyph, size)
+                }
+            };
+            check_size(&class, &size)?;
+            icons.insert(class, span(&glyph, &size));
+        }
+
+        if !(0.1..=0.9).contains(&self.master.fraction) {
+            bail!("master.fraction must be between 0.1 and 0.9");
+        }
+
+        Ok(Config {
+            icons,
+            names: self.names,
+            master: self.master,
+            spiral: self.spiral,
+            autotab: self.autotab,
+            workspaces: self.workspaces,
+            swallow: self.swallow,
+            switch: self.switch,
+            session: self.session,
+            marks: Marks { command: self.marks.command, auto: marks::compile_auto(self.marks.auto)? },
+            scratchpads: scratch::compile(self.scratchpads)?,
+            rules: rules::compile_rules(self.rules)?,
+        })
+    }
+}
+
+// ── Loading ───────────────────────────────────────────────────
+
+pub fn path() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    base.join("i3-helper").join("config.toml")
+}
+
+fn try_load() -> Result<Config> {
+    let path = path();
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Config::builtin(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let raw: RawConfig =
+        toml::from_str(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+    raw.into_config()
+        .with_context(|| format!("Invalid config {}", path.display()))
+}
+
+fn report_error(e: &anyhow::Error, fallback: &str) {
+    eprintln!("i3-helper: config error, using {fallback}: {e:#}");
+    let _ = Cmd::new("notify-send")
+        .args(["-u", "critical", "i3-helper: config error", &format!("{e:#}")])
+        .spawn();
+}
+
+/// Load the config, falling back to built-in defaults on any error.
+pub fn load() -> Config {
+    try_load()
+        .or_else(|e| {
+            report_error(&e, "built-in defaults");
+            Config::builtin()
+        })
+        .unwrap_or_else(|e| {
+            report_error(&e, "minimal defaults");
+            Config::minimal()
+        })
+}
+
+static CONFIG: LazyLock<RwLock<Arc<Config>>> = LazyLock::new(|| RwLock::new(Arc::new(load())));
+
+/// Current process-wide config, loaded on first use.
+pub fn get() -> Arc<Config> {
+    // A poisoned lock still holds a fully-built Arc; keep serving it.
+    CONFIG.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Re-read the file. On error the previous config stays active.
+pub fn reload() -> Result<()> {
+    let cfg = try_load().inspect_err(|e| report_error(e, "previous config"))?;
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(cfg);
+    Ok(())
+}
+
+// ── Hot Reload ────────────────────────────────────────────────
+// Watches the config *directory*: editors commonly save via write-to-temp +
+// rename, which would silently orphan a watch placed on the file's inode.
+
+/// Editors emit several events per save; wait for the burst to settle.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Watch the config file and call `on_change` after each successful reload.
+pub fn spawn_watcher(mut on_change: impl FnMut() + Send + 'static) -> Result<()> {
+    let path = path();
+    let dir = path.parent().context("Config path has no parent")?.to_path_buf();
+    let name = path.file_name().context("Config path has no file name")?.to_owned();
+
+    // A config written later must still be picked up
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let file = inotify_watch(&dir)?;
+    thread::spawn(move || {
+        let mut file = file;
+        // Room for a burst of events; each is 16 bytes + NUL-padded name.
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("i3-helper: config watcher stopped: {e}");
+                    return;
+                }
+            };
+            if !touches(&buf[..n], name.as_bytes()) {
+                continue;
+            }
+            thread::sleep(SETTLE);
+            if reload().is_ok() {
+                eprintln!("i3-helper: reloaded {}", path.display());
+                on_change();
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn inotify_watch(dir: &Path) -> Result<File> {
+    let cdir = CString::new(dir.as_os_str().as_bytes()).context("Config dir contains NUL")?;
+    // SAFETY: plain syscalls; the fd is owned by `File` below and closed on drop.
+    unsafe {
+        let fd = libc::inotify_init1(libc::IN_CLOEXEC);
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("inotify_init1 failed");
+        }
+        let file = File::from_raw_fd(fd);
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE;
+        if libc::inotify_add_watch(fd, cdir.as_ptr(), mask) < 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("Failed to watch {}", dir.display()));
+        }
+        Ok(file)
+    }
+}
+
+/// True if any `inotify_event` in `buf` names `target`.
+fn touches(buf: &[u8], target: &[u8]) -> bool {
+    const HDR: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut off = 0;
+    while off + HDR <= buf.len() {
+        // SAFETY: bounds checked above; the kernel does not guarantee alignment.
+        let ev: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buf[off..].as_ptr().cast()) };
+        let name_end = (off + HDR + ev.len as usize).min(buf.len());
+        let name = &buf[off + HDR..name_end];
+        let name = name.split(|&b| b == 0).next().unwrap_or_default();
+        if name == target {
+            return true;
+        }
+        off = name_end;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config> {
+        toml::from_str::<RawConfig>(text)?.into_config()
+    }
+
+    #[test]
+    fn apps_add_override_and_resize() {
+        let c = parse(
+            r#"
+            [icons]
+            size = "large"
+
+            [icons.apps]
+            Obsidian = "X"                  # added, default size, key lowercased
+            kitty = { size = "small" }      # built-in glyph, new size
+            firefox = { icon = "F" }        # new glyph, built-in size
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.icons["obsidian"], span("X", "large"));
+        assert_eq!(c.icons["kitty"], span("\u{EBCA}", "small"));
+        assert_eq!(c.icons["firefox"], span("F", "x-large"));
+        // Untouched built-ins keep their own size, not [icons] size
+        assert_eq!(c.icons["thunar"], span("\u{F07B}", "x-large"));
+    }
+
+    #[test]
+    fn glyphs_are_escaped_for_pango() {
+        let c = parse("[icons.apps]\nrnd = \"R&D <x>\"").unwrap();
+        assert_eq!(c.icons["rnd"], "<span size='x-large'>R&amp;D &lt;x&gt; </span>");
+    }
+
+    #[test]
+    fn remove_and_reject_bad_entries() {
+        let c = parse("[icons]\nremove = [\"ST\", \"virtualbox\"]").unwrap();
+        assert!(!c.icons.contains_key("st"));
+        assert!(!c.icons.contains_key("virtualbox"));
+        assert!(c.icons.contains_key("kitty"));
+
+        // No built-in glyph to resize
+        assert!(parse("[icons.apps]\nfoo = { size = \"large\" }").is_err());
+        // Sizes that would break out of the span attribute
+        assert!(parse("[icons.apps]\nkitty = { size = \"x'>\" }").is_err());
+        assert!(parse("[icons]\nsize = \"\"").is_err());
+        assert!(parse("[icons]\nbogus = 1").is_err());
+    }
+}
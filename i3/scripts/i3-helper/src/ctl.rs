@@ -0,0 +1,390 @@
+//! Control socket: acknowledged replacement for the SIGUSR1 + request-file
+//! handshake.
+//!
+//! Line-delimited JSON over `$XDG_RUNTIME_DIR/i3-helper/i3-helper.sock`.
+//! Every request line gets exactly one reply line:
+//!
+//!   → {"cmd":"set-mode","mode":"vertical"}
+//!   ← {"ok":true,"mode":"vertical"}
+//!   → {"cmd":"set-mode","mode":"diagonal"}
+//!   ← {"ok":false,"error":"unknown mode 'diagonal'"}
+//!
+//! Clients are served one at a time on a dedicated thread, so two keybinds
+//! firing together are applied in order instead of racing on a shared file.
+
+use crate::{
+    apply_mode, config, dynamic, ensure_conn, find_focused, focused_workspace, marks, master, mode_label,
+    modes, mru, next_mode, parse_mode, refresh_names, socket_path, state, status, wm, ws_key, Scope,
+    MODE_MASTER,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::time::Duration;
+use std::{fs, thread};
+use swayipc::Connection;
+
+/// A stalled client must not wedge the (single-threaded) server.
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+// ── Protocol ──────────────────────────────────────────────────
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub enum Request {
+    SetMode {
+        mode: String,
+        /// Change the default instead of the focused workspace.
+        #[serde(default)]
+        global: bool,
+        /// Target a specific workspace (number or name) instead.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workspace: Option<String>,
+    },
+    CycleMode,
+    MasterPromote,
+    /// Absolute (`"2"`) or relative (`"+1"`, `"-1"`).
+    MasterCount { count: String },
+    RefreshNames,
+    ReloadConfig,
+    /// Focus the first free workspace (see `dynamic`).
+    NextEmpty,
+    /// Move the focused window to the first free workspace and follow it.
+    MoveToNextEmpty,
+    /// Close gaps in workspace numbers once.
+    Renumber,
+    /// Focus the previously focused window (see `mru`).
+    FocusLast,
+    /// Focus the window `count` steps back in focus history.
+    FocusPrev { count: usize },
+    ListMru,
+    /// Mark the focused window and remember it (see `marks`).
+    MarkSet {
+        mark: String,
+        /// Title regex the window must also match when re-marked.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    /// Forget a mark, or every mark of the focused window.
+    MarkUnset {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mark: Option<String>,
+    },
+    ListMarks,
+    GetStatus,
+    /// Bar snapshot (see `status`); with `follow`, one line per change.
+    Status {
+        #[serde(default)]
+        follow: bool,
+    },
+}
+
+impl Request {
+    /// Build a request from `i3-helper msg <COMMAND> [ARG]...` arguments.
+    fn from_args(args: &[String]) -> Result<Self> {
+        let arg = |n: usize| args.get(n).cloned();
+        match args.first().map(String::as_str) {
+            Some("set-mode") => {
+                let mode = arg(1).context("set-mode requires a value: alternating|vertical|horizontal|master|spiral")?;
+                let (mut global, mut workspace) = (false, None);
+                let mut i = 2;
+                while i < args.len() {
+                    match args[i].as_str() {
+                        "--global" | "-g" => global = true,
+                        "--workspace" | "-w" => {
+                            i += 1;
+                            workspace = Some(arg(i).context("--workspace requires a value")?);
+                        }
+                        other => bail!("set-mode: unexpected argument '{other}'"),
+                    }
+                    i += 1;
+                }
+                Ok(Request::SetMode { mode, global, workspace })
+            }
+            Some("cycle-mode") => Ok(Request::CycleMode),
+            Some("master-promote") => Ok(Request::MasterPromote),
+            Some("master-count") => Ok(Request::MasterCount {
+                count: arg(1).context("master-count requires a value: N, +N or -N")?,
+            }),
+            Some("refresh-names") => Ok(Request::RefreshNames),
+            Some("reload-config") => Ok(Request::ReloadConfig),
+            Some("get-status") => Ok(Request::GetStatus),
+            Some("next-empty") => Ok(Request::NextEmpty),
+            Some("move-to-next-empty") => Ok(Request::MoveToNextEmpty),
+            Some("renumber") => Ok(Request::Renumber),
+            Some("focus-last") => Ok(Request::FocusLast),
+            Some("focus-prev") => Ok(Request::FocusPrev {
+                count: match arg(1) {
+                    Some(n) => n.parse().ok().filter(|&n| n > 0).context("focus-prev takes a count ≥ 1")?,
+                    None => 1,
+                },
+            }),
+            Some("list-mru") => Ok(Request::ListMru),
+            Some("mark-set") => Ok(Request::MarkSet {
+                mark: arg(1).context("mark-set requires a mark")?,
+                title: arg(2),
+            }),
+            Some("mark-unset") => Ok(Request::MarkUnset { mark: arg(1) }),
+            Some("list-marks") => Ok(Request::ListMarks),
+            Some(other) => bail!("unknown command '{other}'"),
+            None => bail!("missing command"),
+        }
+    }
+}
+
+// ── Server ────────────────────────────────────────────────────
+
+/// Bind the control socket and serve it on a background thread.
+pub fn spawn_server() -> Result<()> {
+    let path = socket_path();
+    // A socket left behind by a killed instance makes bind() fail with EADDRINUSE.
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind control socket {path}"))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+        .context("Failed to restrict control socket permissions")?;
+
+    thread::spawn(move || {
+        // Own command connection, opened lazily and dropped on error so the
+        // next request reconnects instead of reusing a dead socket.
+        let mut conn: Option<Connection> = None;
+        for stream in listener.incoming() {
+            match stream {
+                Ok(s) => {
+                    if let Err(e) = serve(s, &mut conn) {
+                        eprintln!("i3-helper: control client error: {e:#}");
+                    }
+                }
+                Err(e) => eprintln!("i3-helper: control accept error: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(stream: UnixStream, conn: &mut Option<Connection>) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Request>(&line) {
+            // The connection now belongs to the status publisher
+            Ok(Request::Status { follow: true }) => return status::subscribe(writer),
+            Ok(req) => dispatch(req, conn).unwrap_or_else(|e| {
+                if is_transport(&e) {
+                    *conn = None;
+                }
+                json!({ "ok": false, "error": format!("{e:#}") })
+            }),
+            Err(e) => json!({ "ok": false, "error": format!("bad request: {e}") }),
+        };
+        writeln!(writer, "{reply}")?;
+    }
+
+    Ok(())
+}
+
+/// Whether `e` came from the i3 socket itself rather than from a bad
+/// request or a command i3 rejected; only then is the connection reopened.
+fn is_transport(e: &anyhow::Error) -> bool {
+    e.chain().any(|c| match c.downcast_ref::<swayipc::Error>() {
+        Some(
+            swayipc::Error::CommandFailed(_)
+            | swayipc::Error::CommandParse(_)
+            | swayipc::Error::SubscriptionFailed(_),
+        ) => false,
+        Some(_) => true,
+        None => c.is::<std::io::Error>(),
+    })
+}
+
+fn dispatch(req: Request, conn: &mut Option<Connection>) -> Result<Value> {
+    match req {
+        Request::SetMode { mode, global, workspace } => {
+            let m = parse_mode(&mode).with_context(|| format!("unknown mode '{mode}'"))?;
+            let scope = match (global, workspace) {
+                (true, Some(_)) => bail!("--global and --workspace are mutually exclusive"),
+                (true, None) => Scope::Global,
+                (false, Some(ws)) => Scope::Workspace(ws),
+                (false, None) => Scope::Focused,
+            };
+            apply_mode(m, scope);
+            Ok(json!({ "ok": true, "mode": mode_label(m) }))
+        }
+        Request::CycleMode => {
+            let m = next_mode();
+            apply_mode(m, Scope::Focused);
+            Ok(json!({ "ok": true, "mode": mode_label(m) }))
+        }
+        Request::MasterPromote => {
+            let c = ensure_conn(conn)?;
+            let tree = c.get_tree().context("get_tree failed")?;
+            let ws = focused_workspace(&tree).context("no focused workspace")?;
+            if modes().get(&ws_key(ws)) != MODE_MASTER {
+                bail!("focused workspace is not in master mode");
+            }
+            let focused = find_focused(&tree).context("no focused window")?;
+            master::promote(c, ws, focused.id)?;
+            Ok(json!({ "ok": true }))
+        }
+        Request::MasterCount { count } => {
+            let default = config::get().master.count;
+            let (key, n) = {
+                let mut m = modes();
+                let key = m.focused.clone();
+                if key.is_empty() {
+                    bail!("no focused workspace yet");
+                }
+                if m.get(&key) != MODE_MASTER {
+                    bail!("focused workspace is not in master mode");
+                }
+                let current = m.nmaster(&key, default) as i64;
+                let n = match count.strip_prefix('+') {
+                    Some(d) => current + d.parse::<i64>()?,
+                    None if count.starts_with('-') => current + count.parse::<i64>()?,
+                    None => count.parse::<i64>()?,
+                }
+                .max(1) as usize;
+                m.nmaster.insert(key.clone(), n);
+                (key, n)
+            };
+            state::save();
+            master::reflow(ensure_conn(conn)?, &key, n, config::get().master.fraction)?;
+            Ok(json!({ "ok": true, "count": n }))
+        }
+        Request::RefreshNames => {
+            refresh_names(ensure_conn(conn)?)?;
+            Ok(json!({ "ok": true }))
+        }
+        Request::ReloadConfig => {
+            config::reload()?;
+            refresh_names(ensure_conn(conn)?)?;
+            Ok(json!({ "ok": true, "path": config::path() }))
+        }
+        Request::NextEmpty | Request::MoveToNextEmpty => {
+            let take = matches!(req, Request::MoveToNextEmpty);
+            let n = dynamic::goto_next_empty(ensure_conn(conn)?, take)?;
+            Ok(json!({ "ok": true, "workspace": n }))
+        }
+        Request::Renumber => {
+            let c = ensure_conn(conn)?;
+            let tree = c.get_tree().context("get_tree failed")?;
+            let moved = dynamic::renumber(c, &tree)?;
+            Ok(json!({ "ok": true, "renumbered": moved }))
+        }
+        Request::FocusLast | Request::FocusPrev { .. } => {
+            let n = match req {
+                Request::FocusPrev { count } => count,
+                _ => 1,
+            };
+            let id = mru::focus_prev(ensure_conn(conn)?, n)?;
+            Ok(json!({ "ok": true, "id": id }))
+        }
+        Request::ListMru => Ok(json!({ "ok": true, "windows": mru::list(ensure_conn(conn)?)? })),
+        Request::MarkSet { mark, title } => {
+            let criteria = marks::set(ensure_conn(conn)?, &mark, title)?;
+            Ok(json!({ "ok": true, "mark": mark, "criteria": criteria }))
+        }
+        Request::MarkUnset { mark } => {
+            let removed = marks::unset(ensure_conn(conn)?, mark.as_deref())?;
+            Ok(json!({ "ok": true, "removed": removed }))
+        }
+        Request::ListMarks => Ok(json!({ "ok": true, "marks": marks::list(ensure_conn(conn)?)? })),
+        Request::Status { .. } => Ok(status::reply()),
+        Request::GetStatus => {
+            let m = modes();
+            let per_ws: serde_json::Map<String, Value> = m
+                .per_ws
+                .iter()
+                .map(|(ws, &mode)| (ws.clone(), mode_label(mode).into()))
+                .collect();
+            Ok(json!({
+                "ok": true,
+                "pid": std::process::id(),
+                "wm": wm::get().name(),
+                "workspace": m.focused,
+                "mode": mode_label(m.focused_mode()),
+                "default": mode_label(m.default),
+                "workspaces": per_ws,
+            }))
+        }
+    }
+}
+
+// ── Client ────────────────────────────────────────────────────
+
+/// Connect to the running daemon's control socket.
+pub fn connect() -> Result<UnixStream> {
+    let path = socket_path();
+    UnixStream::connect(&path).with_context(|| format!("Failed to connect to {path}"))
+}
+
+/// Send one request to the running daemon and wait for its reply.
+pub fn send(req: &Request) -> Result<Value> {
+    let mut stream = connect()?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+
+    let mut line = serde_json::to_string(req)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).context("Failed to send request")?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .context("No reply from daemon")?;
+    serde_json::from_str(&reply).context("Malformed reply from daemon")
+}
+
+/// Print a reply on stdout; an `ok: false` reply exits with status 1.
+pub fn report(reply: Value) -> Result<()> {
+    if reply["ok"].as_bool() == Some(true) {
+        println!("{reply}");
+        return Ok(());
+    }
+    eprintln!(
+        "i3-helper: {}",
+        reply["error"].as_str().unwrap_or("request failed")
+    );
+    std::process::exit(1);
+}
+
+/// `i3-helper msg <COMMAND> [ARG]`
+pub fn run_client(args: &[String]) -> Result<()> {
+    let req = match Request::from_args(args) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("i3-helper msg: {e}");
+            std::process::exit(1);
+        }
+    };
+    report(send(&req)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn only_transport_errors_drop_the_connection() {
+        let mut conn = None;
+        let e = dispatch(Request::SetMode { mode: "bogus".into(), global: false, workspace: None }, &mut conn);
+        assert!(!is_transport(&e.unwrap_err()));
+        let rejected = anyhow::Error::new(swayipc::Error::CommandFailed("no such con".into()));
+        assert!(!is_transport(&rejected.context("focus failed")));
+
+        let broken = io::Error::new(io::ErrorKind::BrokenPipe, "i3 went away");
+        assert!(is_transport(&anyhow::Error::new(swayipc::Error::Io(broken)).context("get_tree failed")));
+        let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        assert!(is_transport(&anyhow::Error::new(eof)));
+    }
+}
@@ -0,0 +1,189 @@
+//! Dynamic workspaces: compact numbering and jump to a fresh workspace.
+//!
+//! With `workspaces.dynamic = true` the daemon renumbers non-empty
+//! workspaces after every change so each output's are contiguous, counting
+//! up from the lowest number on that output (1, 4, 7 → 1, 2, 3; a second
+//! output at 11, 15 → 11, 12). Numbers held by other workspaces are
+//! skipped, never stolen. Only the number prefix is rewritten, so icon
+//! labels ("4: 󰈹" → "2: 󰈹") and per-workspace modes carry over.
+//!
+//! `msg renumber` does the same once; `msg next-empty` and
+//! `msg move-to-next-empty` go to (or take the focused window to) the
+//! first free number on the focused output.
+
+use crate::{collect_leaves, collect_workspaces, focused_workspace, modes, spiral, state};
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use swayipc::{Connection, Node};
+
+/// A numbered workspace, as far as renumbering cares.
+#[derive(Debug)]
+struct Ws {
+    num: i32,
+    name: String,
+    empty: bool,
+}
+
+/// Numbered workspaces per output, in tree order.
+fn outputs(tree: &Node) -> Vec<Vec<Ws>> {
+    tree.nodes
+        .iter()
+        .filter(|o| o.name.as_deref() != Some("__i3"))
+        .map(|o| {
+            let mut ws_buf = Vec::new();
+            collect_workspaces(o, &mut ws_buf);
+            ws_buf
+                .iter()
+                .filter_map(|ws| {
+                    let num = ws.num.filter(|&n| n >= 0)?;
+                    let mut leaves = Vec::new();
+                    collect_leaves(ws, &mut leaves);
+                    Some(Ws { num, name: ws.name.clone().unwrap_or_default(), empty: leaves.is_empty() })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `name` with its number prefix replaced by `num`.
+fn renamed(name: &str, num: i32) -> String {
+    let rest = name.trim_start_matches(|c: char| c.is_ascii_digit());
+    format!("{num}{rest}")
+}
+
+/// (old number, old name, new number, new name) for every workspace that
+/// moves, in the order the renames must run: ascending, so a target number
+/// is always free by the time it is taken.
+fn plan(outputs: &[Vec<Ws>]) -> Vec<(i32, String, i32, String)> {
+    let all: Vec<&Ws> = outputs.iter().flatten().collect();
+    // Targets handed out to earlier outputs
+    let mut claimed = BTreeSet::new();
+    let mut moves = Vec::new();
+    for list in outputs {
+        let mut movable: Vec<&Ws> = list.iter().filter(|w| !w.empty).collect();
+        movable.sort_by_key(|w| w.num);
+        let base = match movable.first() {
+            Some(w) => w.num,
+            None => continue,
+        };
+        // Held by someone else: empty workspaces here, anything elsewhere
+        let taken: BTreeSet<i32> = all
+            .iter()
+            .filter(|w| !movable.iter().any(|m| std::ptr::eq(*m, **w)))
+            .map(|w| w.num)
+            .collect();
+        let mut next = base;
+        for w in movable {
+            while taken.contains(&next) || claimed.contains(&next) {
+                next += 1;
+            }
+            claimed.insert(next);
+            if next != w.num {
+                moves.push((w.num, w.name.clone(), next, renamed(&w.name, next)));
+            }
+            next += 1;
+        }
+    }
+    moves.sort_by_key(|m| m.0);
+    moves
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Compact workspace numbers (see module docs). Returns how many moved.
+pub fn renumber(cmd: &mut Connection, tree: &Node) -> Result<usize> {
+    let moves = plan(&outputs(tree));
+    if moves.is_empty() {
+        return Ok(0);
+    }
+    let cmds: Vec<String> = moves
+        .iter()
+        .map(|(_, old, _, new)| format!("rename workspace {} to {}", quote(old), quote(new)))
+        .collect();
+    cmd.run_command(cmds.join("; "))?;
+
+    // Per-workspace state is keyed by number: follow the rename
+    {
+        let mut m = modes();
+        for (old, _, new, _) in &moves {
+            m.rekey(&old.to_string(), &new.to_string());
+        }
+    }
+    for (old, _, new, _) in &moves {
+        spiral::rekey(&old.to_string(), &new.to_string());
+    }
+    state::save();
+    Ok(moves.len())
+}
+
+/// First free number on the focused output, or `None` when the focused
+/// workspace is empty already.
+fn next_empty(tree: &Node) -> Option<i32> {
+    let ws = focused_workspace(tree)?;
+    let lists = outputs(tree);
+    let used: BTreeSet<i32> = lists.iter().flatten().map(|w| w.num).collect();
+    let here = lists
+        .iter()
+        .find(|l| l.iter().any(|w| Some(w.num) == ws.num))
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    if here.iter().any(|w| Some(w.num) == ws.num && w.empty) {
+        return None;
+    }
+    let base = here.iter().map(|w| w.num).min().unwrap_or(1).max(1);
+    (base..).find(|n| !used.contains(n))
+}
+
+/// Focus (or, with `take`, move the focused window to and follow it to)
+/// the first free workspace. Returns the workspace number.
+pub fn goto_next_empty(cmd: &mut Connection, take: bool) -> Result<Option<i32>> {
+    let tree = cmd.get_tree().context("get_tree failed")?;
+    let n = match next_empty(&tree) {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+    let c = if take {
+        format!("move container to workspace number {n}; workspace number {n}")
+    } else {
+        format!("workspace number {n}")
+    };
+    for outcome in cmd.run_command(c)? {
+        outcome.context("workspace switch failed")?;
+    }
+    Ok(Some(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ws(num: i32, name: &str, empty: bool) -> Ws {
+        Ws { num, name: name.into(), empty }
+    }
+
+    #[test]
+    fn gaps_close_and_labels_survive() {
+        let out = vec![vec![ws(1, "1: a", false), ws(4, "4: b", false), ws(7, "7", false)]];
+        let moves: Vec<(i32, i32, String)> = plan(&out).into_iter().map(|(o, _, n, name)| (o, n, name)).collect();
+        assert_eq!(moves, [(4, 2, "2: b".into()), (7, 3, "3".into())]);
+    }
+
+    #[test]
+    fn outputs_keep_their_range_and_numbers_in_use_are_skipped() {
+        let out = vec![
+            // Focused empty workspace 2 stays where it is
+            vec![ws(1, "1", false), ws(2, "2", true), ws(5, "5: x", false)],
+            vec![ws(11, "11", false), ws(15, "15", false)],
+        ];
+        let moves: Vec<(i32, i32)> = plan(&out).into_iter().map(|(o, _, n, _)| (o, n)).collect();
+        assert_eq!(moves, [(5, 3), (15, 12)]);
+
+        // Interleaved outputs never race for the same number
+        let out = vec![vec![ws(4, "4", false), ws(9, "9", false)], vec![ws(5, "5", false), ws(8, "8", false)]];
+        let moves: Vec<(i32, i32)> = plan(&out).into_iter().map(|(o, _, n, _)| (o, n)).collect();
+        assert_eq!(moves, [(8, 7), (9, 6)]);
+        assert!(plan(&[vec![ws(3, "3", false), ws(4, "4", false)]]).is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! Single-instance guard.
+//!
+//! The running daemon holds an exclusive `flock(2)` on `i3-helper.lock` in a
+//! private (0700) runtime directory and keeps its PID in that file. Holding
+//! the lock is what makes a process *the* instance: the kernel drops it when
+//! the process dies, so a stale PID can never be mistaken for a live daemon.
+//!
+//! Hand-off (`--replace`, the default for `exec_always`): the lock holder's
+//! `/proc/<pid>/exe` must be this very binary (same path) before it is sent
+//! SIGTERM, and the new instance starts only once the lock has actually
+//! been released.
+//!
+//! A daemon that doesn't hold the lock — one started by a build from before
+//! the lock existed — is found by scanning `/proc` for other processes of
+//! this binary that aren't one-shot client invocations, and replaced the
+//! same way once the lock is ours.
+
+use crate::{private_dir, proc_cmdline};
+use anyhow::{bail, Context, Result};
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a SIGTERMed instance gets to release the lock before SIGKILL.
+const TERM_TIMEOUT: Duration = Duration::from_secs(2);
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+const POLL: Duration = Duration::from_millis(10);
+
+/// What to do when another instance is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Stop it and take over.
+    Replace,
+    /// Leave it running and exit.
+    NoReplace,
+}
+
+/// Held for the daemon's lifetime; dropping it releases the lock.
+pub struct Guard {
+    _file: File,
+}
+
+fn lock_path() -> String {
+    format!("{}/i3-helper.lock", private_dir())
+}
+
+/// Create the private runtime dir, or check an existing one is ours and
+/// not accessible to anyone else (it may live under a shared `/tmp`).
+fn ensure_private_dir() -> Result<()> {
+    let dir = private_dir();
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {dir}")),
+    }
+    let meta = fs::symlink_metadata(&dir).with_context(|| format!("Failed to stat {dir}"))?;
+    // SAFETY: getuid(2) cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if !meta.is_dir() || meta.uid() != uid {
+        bail!("{dir} is not a directory owned by uid {uid}");
+    }
+    if meta.mode() & 0o077 != 0 {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {dir}"))?;
+    }
+    Ok(())
+}
+
+#[inline]
+fn try_lock(file: &File, op: libc::c_int) -> bool {
+    // SAFETY: valid fd owned by `file`; flock has no memory effects.
+    unsafe { libc::flock(file.as_raw_fd(), op | libc::LOCK_NB) == 0 }
+}
+
+fn read_pid(file: &mut File) -> Option<i32> {
+    let mut s = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut s).ok()?;
+    s.trim().parse().ok().filter(|&p| p > 0)
+}
+
+/// Subcommands (`argv[1]`) and flags that make an invocation a one-shot
+/// client rather than a daemon; kept in step with `main`.
+const CLIENT_COMMANDS: &[&str] = &["msg", "status", "layout", "session", "switch", "mark", "scratch"];
+const CLIENT_FLAGS: &[&str] = &["--set-mode", "-s", "--help", "-h"];
+
+/// Binary behind `/proc/<pid>/exe` (`self` for this process), ignoring the
+/// " (deleted)" suffix a rebuilt binary leaves behind.
+fn exe_path(pid: &str) -> Option<PathBuf> {
+    let target = fs::read_link(format!("/proc/{pid}/exe")).ok()?;
+    let target = target.to_string_lossy();
+    Some(PathBuf::from(target.trim_end_matches(" (deleted)")))
+}
+
+/// Whether `pid` runs this binary: same path, not just the same name.
+fn is_i3_helper(pid: i32) -> bool {
+    match (exe_path(&pid.to_string()), exe_path("self")) {
+        (Some(theirs), Some(ours)) => theirs == ours,
+        _ => false,
+    }
+}
+
+fn is_daemon(argv: &[String]) -> bool {
+    let client = argv.get(1).is_some_and(|a| CLIENT_COMMANDS.contains(&a.as_str()))
+        || argv.iter().skip(1).any(|a| CLIENT_FLAGS.contains(&a.as_str()));
+    !argv.is_empty() && !client
+}
+
+/// Other daemons of this binary, whether or not they hold the lock.
+fn other_daemons() -> Vec<i32> {
+    let me = std::process::id() as i32;
+    let entries = match fs::read_dir("/proc") {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<i32>().ok())
+        .filter(|&pid| pid != me && is_i3_helper(pid) && is_daemon(&proc_cmdline(Some(pid))))
+        .collect()
+}
+
+#[inline]
+fn alive(pid: i32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists.
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// Wait up to `timeout` for `pid` to exit.
+fn wait_gone(pid: i32, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if !alive(pid) {
+            return true;
+        }
+        thread::sleep(POLL);
+    }
+    false
+}
+
+/// Stop a daemon that doesn't hold the lock (so only its exit says it's
+/// gone). Callers have checked it is an i3-helper daemon.
+fn stop_lockless(pid: i32) -> Result<()> {
+    // SAFETY: pid > 0 and verified by the caller.
+    unsafe { libc::kill(pid, libc::SIGTERM) };
+    if wait_gone(pid, TERM_TIMEOUT) {
+        return Ok(());
+    }
+    eprintln!("i3-helper: pid {pid} ignored SIGTERM, killing it");
+    // SAFETY: as above.
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+    if !wait_gone(pid, KILL_TIMEOUT) {
+        bail!("pid {pid} did not exit");
+    }
+    Ok(())
+}
+
+/// Wait up to `timeout` for the lock to become free, taking it if so.
+fn wait_for_lock(file: &File, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if try_lock(file, libc::LOCK_EX) {
+            return true;
+        }
+        thread::sleep(POLL);
+    }
+    false
+}
+
+/// Become the single running instance. `Ok(None)` means another instance is
+/// running and `policy` says to leave it alone.
+pub fn acquire(policy: Policy) -> Result<Option<Guard>> {
+    ensure_private_dir()?;
+    let path = lock_path();
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("Failed to open {path}"))?;
+
+    if !try_lock(&file, libc::LOCK_EX) {
+        // The holder writes its PID right after locking; give it a moment.
+        let mut pid = None;
+        for _ in 0..50 {
+            pid = read_pid(&mut file);
+            if pid.is_some() {
+                break;
+            }
+            thread::sleep(POLL);
+        }
+        let pid = pid.context("another instance holds the lock but left no PID")?;
+
+        if policy == Policy::NoReplace {
+            eprintln!("i3-helper: already running (pid={pid})");
+            return Ok(None);
+        }
+        if !is_i3_helper(pid) {
+            bail!("lock held by pid {pid}, which is not i3-helper; refusing to signal it");
+        }
+
+        // SAFETY: pid > 0 (read_pid) and verified above to be an i3-helper.
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+        if !wait_for_lock(&file, TERM_TIMEOUT) {
+            eprintln!("i3-helper: pid {pid} ignored SIGTERM, killing it");
+            // SAFETY: as above; it still holds the lock, so it is still that process.
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            if !wait_for_lock(&file, KILL_TIMEOUT) {
+                bail!("pid {pid} did not release {path}");
+            }
+        }
+    }
+
+    // Daemons from before the lock never took it
+    for pid in other_daemons() {
+        if policy == Policy::NoReplace {
+            eprintln!("i3-helper: already running without the lock (pid={pid})");
+            return Ok(None);
+        }
+        stop_lockless(pid)?;
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id()).context("Failed to write PID to lock file")?;
+    Ok(Some(Guard { _file: file }))
+}
+
+/// PID of the running instance, if there is one. A PID is only reported
+/// while its owner still holds the lock and is an i3-helper binary.
+pub fn running_pid() -> Option<i32> {
+    let path = lock_path();
+    if !Path::new(&path).exists() {
+        return None;
+    }
+    let mut file = File::open(&path).ok()?;
+    if try_lock(&file, libc::LOCK_SH) {
+        return None; // nobody holds it: stale file
+    }
+    read_pid(&mut file).filter(|&pid| is_i3_helper(pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn daemons_and_clients_are_told_apart() {
+        assert!(is_daemon(&argv(&["i3-helper"])));
+        assert!(is_daemon(&argv(&["i3-helper", "--mode", "vertical", "--replace"])));
+        assert!(!is_daemon(&argv(&["i3-helper", "msg", "cycle-mode"])));
+        assert!(!is_daemon(&argv(&["i3-helper", "status", "--follow"])));
+        assert!(!is_daemon(&argv(&["i3-helper", "--set-mode", "master"])));
+        // Kernel threads and zombies have no command line
+        assert!(!is_daemon(&[]));
+    }
+
+    #[test]
+    fn identity_is_the_binary_path() {
+        assert!(is_i3_helper(std::process::id() as i32));
+        // The test runner's parent (cargo, a shell) is another binary
+        assert!(!is_i3_helper(std::os::unix::process::parent_id() as i32));
+    }
+}
@@ -0,0 +1,430 @@
+//! Named workspace layouts: `i3-helper layout save|restore|list`.
+//!
+//! `save` turns a workspace subtree into an i3 `append_layout` file under
+//! `~/.config/i3-helper/layouts/<name>.json`: containers keep their layout
+//! and size, every window becomes a placeholder that swallows its
+//! class/instance. A sidecar `<name>.apps.json` records each window's
+//! command line (`_NET_WM_PID` → `/proc/<pid>/cmdline`).
+//!
+//! `restore` appends the layout, moves windows that already exist on the
+//! target workspace into their placeholders (i3 only swallows *new*
+//! windows; `--adopt-all` takes them from every workspace), and with
+//! `--launch` starts the apps that are still missing. Placeholders carry a hidden
+//! `_i3h_layout_*` mark so the two can be matched up. i3 only: Sway has no
+//! `append_layout`.
+
+use crate::{
+    collect_leaves, collect_workspaces, config, find_workspace, focused_workspace, proc_cmdline, window_pid,
+    workspace_of, wm, ws_key,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command as Cmd, Stdio};
+use swayipc::{Node, NodeLayout};
+
+const MARK_PREFIX: &str = "_i3h_layout";
+
+/// A saved window: what its placeholder swallows and how to start it.
+#[derive(Debug, Serialize, Deserialize)]
+struct App {
+    mark: String,
+    class: String,
+    instance: Option<String>,
+    command: Vec<String>,
+}
+
+fn dir() -> PathBuf {
+    let cfg = config::path();
+    cfg.parent().map(|d| d.join("layouts")).unwrap_or_else(|| PathBuf::from("layouts"))
+}
+
+/// (`<name>.json`, `<name>.apps.json`)
+fn paths(name: &str) -> Result<(PathBuf, PathBuf)> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        bail!("invalid layout name '{name}'");
+    }
+    let d = dir();
+    Ok((d.join(format!("{name}.json")), d.join(format!("{name}.apps.json"))))
+}
+
+/// Double-quoted i3 command argument.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Exact-match criterion, as i3-save-tree writes them.
+#[inline]
+fn exact(s: &str) -> String {
+    format!("^{}$", regex::escape(s))
+}
+
+fn layout_name(l: NodeLayout) -> &'static str {
+    match l {
+        NodeLayout::SplitV => "splitv",
+        NodeLayout::Tabbed => "tabbed",
+        NodeLayout::Stacked => "stacked",
+        _ => "splith",
+    }
+}
+
+/// Placeholder for window `leaf`, or `None` if it has nothing to swallow by
+/// (no X11 class).
+fn placeholder(leaf: &Node, name: &str, apps: &mut Vec<App>) -> Option<Value> {
+    let wp = leaf.window_properties.as_ref()?;
+    let class = wp.class.clone()?;
+    let mark = format!("{MARK_PREFIX}_{name}_{}", apps.len());
+
+    let mut swallow = Map::new();
+    swallow.insert("class".into(), exact(&class).into());
+    if let Some(i) = &wp.instance {
+        swallow.insert("instance".into(), exact(i).into());
+    }
+    let mut con = Map::new();
+    con.insert("type".into(), "con".into());
+    con.insert("swallows".into(), json!([swallow]));
+    con.insert("marks".into(), json!([mark]));
+    if let Some(p) = leaf.percent {
+        con.insert("percent".into(), p.into());
+    }
+    if let Some(n) = &leaf.name {
+        con.insert("name".into(), n.clone().into());
+    }
+
+    apps.push(App { mark, class, instance: wp.instance.clone(), command: proc_cmdline(window_pid(leaf)) });
+    Some(con.into())
+}
+
+/// Tiling subtree of `node` in append_layout form.
+fn serialize(node: &Node, name: &str, apps: &mut Vec<App>) -> Option<Value> {
+    if node.nodes.is_empty() {
+        return placeholder(node, name, apps);
+    }
+    let children: Vec<Value> = node.nodes.iter().filter_map(|c| serialize(c, name, apps)).collect();
+    if children.is_empty() {
+        return None;
+    }
+    let mut con = Map::new();
+    con.insert("type".into(), "con".into());
+    con.insert("layout".into(), layout_name(node.layout).into());
+    if let Some(p) = node.percent {
+        con.insert("percent".into(), p.into());
+    }
+    con.insert("nodes".into(), children.into());
+    Some(con.into())
+}
+
+/// Top-level layout objects for workspace `ws`: one container holding the
+/// tiling tree (so the workspace's own split direction survives), then one
+/// `floating_con` per floating window.
+fn serialize_workspace(ws: &Node, name: &str, apps: &mut Vec<App>) -> Vec<Value> {
+    let mut out = Vec::new();
+    if let Some(mut tiling) = serialize(ws, name, apps) {
+        tiling.as_object_mut().map(|o| o.remove("percent"));
+        out.push(tiling);
+    }
+    for fc in &ws.floating_nodes {
+        let mut leaves = Vec::new();
+        collect_leaves(fc, &mut leaves);
+        for leaf in leaves {
+            if let Some(p) = placeholder(leaf, name, apps) {
+                let r = &fc.rect;
+                out.push(json!({
+                    "type": "floating_con",
+                    "floating": "user_on",
+                    "rect": { "x": r.x, "y": r.y, "width": r.width, "height": r.height },
+                    "nodes": [p],
+                }));
+            }
+        }
+    }
+    out
+}
+
+fn save(name: &str, workspace: Option<&str>) -> Result<()> {
+    let (layout_path, apps_path) = paths(name)?;
+    let mut conn = wm::connect()?;
+    let tree = conn.get_tree().context("get_tree failed")?;
+    let ws = match workspace {
+        Some(key) => find_workspace(&tree, key).with_context(|| format!("no workspace '{key}'"))?,
+        None => focused_workspace(&tree).context("no focused workspace")?,
+    };
+
+    let mut apps = Vec::new();
+    let objects = serialize_workspace(ws, name, &mut apps);
+    if objects.is_empty() {
+        bail!("workspace '{}' has no windows to save", ws_key(ws));
+    }
+
+    fs::create_dir_all(dir()).with_context(|| format!("Failed to create {}", dir().display()))?;
+    // append_layout takes a sequence of JSON objects, like i3-save-tree writes
+    let text: Vec<String> = objects
+        .iter()
+        .map(serde_json::to_string_pretty)
+        .collect::<Result<_, _>>()?;
+    fs::write(&layout_path, text.join("\n") + "\n")
+        .with_context(|| format!("Failed to write {}", layout_path.display()))?;
+    fs::write(&apps_path, serde_json::to_string_pretty(&apps)?)
+        .with_context(|| format!("Failed to write {}", apps_path.display()))?;
+
+    println!("saved {} window(s) from workspace '{}' to {}", apps.len(), ws_key(ws), layout_path.display());
+    Ok(())
+}
+
+fn matches(leaf: &Node, app: &App) -> bool {
+    let wp = match &leaf.window_properties {
+        Some(wp) => wp,
+        None => return false,
+    };
+    wp.class.as_deref() == Some(app.class.as_str())
+        && (app.instance.is_none() || wp.instance == app.instance)
+}
+
+/// Open windows that may fill the placeholders: those on the workspace the
+/// layout landed on (found through its marks), or every workspace's with
+/// `adopt_all`. Placeholders themselves never qualify.
+fn candidates<'a>(tree: &'a Node, apps: &[App], adopt_all: bool) -> Vec<&'a Node> {
+    let mut ws_buf = Vec::new();
+    collect_workspaces(tree, &mut ws_buf);
+    let mut all = Vec::new();
+    for ws in ws_buf {
+        collect_leaves(ws, &mut all);
+    }
+    let placeholder = |l: &Node| l.marks.iter().any(|m| m.starts_with(MARK_PREFIX));
+
+    let target = if adopt_all {
+        None
+    } else {
+        all.iter()
+            .find(|l| l.marks.iter().any(|m| apps.iter().any(|a| &a.mark == m)))
+            .and_then(|l| workspace_of(tree, l.id))
+            .or_else(|| focused_workspace(tree))
+    };
+    let mut leaves = Vec::new();
+    match target {
+        Some(ws) => collect_leaves(ws, &mut leaves),
+        None if adopt_all => leaves = all,
+        None => {}
+    }
+    leaves.retain(|l| !placeholder(l));
+    leaves
+}
+
+fn restore(name: &str, workspace: Option<&str>, launch: bool, adopt_all: bool) -> Result<()> {
+    let (layout_path, apps_path) = paths(name)?;
+    if !layout_path.exists() {
+        bail!("no saved layout '{name}' ({})", layout_path.display());
+    }
+    let apps: Vec<App> = match fs::read_to_string(&apps_path) {
+        Ok(t) => serde_json::from_str(&t).with_context(|| format!("Failed to parse {}", apps_path.display()))?,
+        Err(_) => Vec::new(),
+    };
+
+    let mut conn = wm::connect()?;
+    if wm::detect(&mut conn) == wm::Wm::Sway {
+        bail!("sway does not support append_layout");
+    }
+
+    let mut c = String::new();
+    if let Some(ws) = workspace {
+        match ws.parse::<i32>() {
+            Ok(n) => c.push_str(&format!("workspace number {n}; ")),
+            Err(_) => c.push_str(&format!("workspace {}; ", quote(ws))),
+        }
+    }
+    c.push_str(&format!("append_layout {}", quote(&layout_path.to_string_lossy())));
+    for outcome in conn.run_command(&c)? {
+        outcome.context("append_layout failed")?;
+    }
+
+    // Adopt windows that are already open: move each into its placeholder,
+    // then close the placeholder.
+    let tree = conn.get_tree().context("get_tree failed")?;
+    let leaves = candidates(&tree, &apps, adopt_all);
+
+    let mut used = HashSet::new();
+    let mut cmds = Vec::new();
+    let mut missing = Vec::new();
+    for app in &apps {
+        match leaves.iter().find(|l| !used.contains(&l.id) && matches(l, app)) {
+            Some(w) => {
+                used.insert(w.id);
+                cmds.push(format!(
+                    "[con_id={}] move container to mark {m}; [con_mark=\"^{m}$\"] kill",
+                    w.id,
+                    m = app.mark
+                ));
+            }
+            None => missing.push(app),
+        }
+    }
+    if !cmds.is_empty() {
+        for outcome in conn.run_command(cmds.join("; "))? {
+            outcome.context("moving open windows into the layout failed")?;
+        }
+    }
+
+    for app in &missing {
+        if !launch {
+            continue;
+        }
+        let Some((prog, args)) = app.command.split_first() else {
+            eprintln!("i3-helper layout: no command recorded for {}", app.class);
+            continue;
+        };
+        let spawned = Cmd::new(prog)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Err(e) = spawned {
+            eprintln!("i3-helper layout: failed to launch {prog}: {e}");
+        }
+    }
+
+    println!(
+        "restored '{name}': {} window(s) adopted, {} {}",
+        used.len(),
+        missing.len(),
+        if launch { "launched" } else { "placeholder(s) waiting (use --launch)" }
+    );
+    Ok(())
+}
+
+fn list() -> Result<()> {
+    let mut names: Vec<String> = match fs::read_dir(dir()) {
+        Ok(rd) => rd
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|n| !n.ends_with(".apps.json"))
+            .filter_map(|n| n.strip_suffix(".json").map(str::to_string))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    for n in names {
+        println!("{n}");
+    }
+    Ok(())
+}
+
+/// `i3-helper layout save <name> [--workspace N]`
+/// `i3-helper layout restore <name> [--workspace N] [--launch] [--adopt-all]`
+/// `i3-helper layout list`
+pub fn run_client(args: &[String]) -> Result<()> {
+    let (mut workspace, mut launch, mut adopt_all) = (None, false, false);
+    let mut positional = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--workspace" | "-w" => {
+                i += 1;
+                workspace = Some(args.get(i).context("--workspace requires a value")?.as_str());
+            }
+            "--launch" => launch = true,
+            "--adopt-all" => adopt_all = true,
+            other => positional.push(other),
+        }
+        i += 1;
+    }
+
+    match positional.as_slice() {
+        ["save", name] => save(name, workspace),
+        ["restore", name] => restore(name, workspace, launch, adopt_all),
+        ["list"] => list(),
+        _ => {
+            eprintln!("Usage: i3-helper layout save <name> [--workspace N]");
+            eprintln!("       i3-helper layout restore <name> [--workspace N] [--launch] [--adopt-all]");
+            eprintln!("       i3-helper layout list");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{node, raw, x11};
+
+    #[test]
+    fn workspace_becomes_swallowing_placeholders() {
+        let mut col = raw(20, "con");
+        col["layout"] = "splitv".into();
+        col["nodes"] = json!([x11(2, "kitty", "kitty", "zsh"), x11(3, "Org.gnome.Nautilus", "org.gnome.Nautilus", "~")]);
+        let mut floating = raw(30, "floating_con");
+        floating["nodes"] = json!([x11(4, "mpv", "gl", "video")]);
+        let mut ws = raw(10, "workspace");
+        ws["nodes"] = json!([x11(1, "firefox", "Navigator", "news"), col]);
+        ws["floating_nodes"] = json!([floating]);
+
+        let mut apps = Vec::new();
+        let out = serialize_workspace(&node(ws), "dev", &mut apps);
+
+        assert_eq!(out.len(), 2, "tiling root + one floating window");
+        let root = &out[0];
+        assert_eq!(root["layout"], "splith");
+        assert!(root.get("percent").is_none());
+        assert_eq!(root["nodes"][0]["swallows"][0]["instance"], "^Navigator$");
+        assert_eq!(root["nodes"][1]["layout"], "splitv");
+        // Regex metacharacters in the class are escaped
+        assert_eq!(root["nodes"][1]["nodes"][1]["swallows"][0]["class"], r"^Org\.gnome\.Nautilus$");
+        assert_eq!(out[1]["type"], "floating_con");
+        assert_eq!(out[1]["nodes"][0]["marks"][0], "_i3h_layout_dev_3");
+
+        let classes: Vec<&str> = apps.iter().map(|a| a.class.as_str()).collect();
+        assert_eq!(classes, ["firefox", "kitty", "Org.gnome.Nautilus", "mpv"]);
+    }
+
+    #[test]
+    fn i3_windows_get_their_command_from_net_wm_pid() {
+        // i3 sends no pid; the X11 property (cached here) stands in
+        let me = std::process::id() as i32;
+        let mut win = x11(5, "kitty", "kitty", "zsh");
+        win["window"] = 0x4c0001.into();
+        crate::X11_PIDS.lock().unwrap().insert(0x4c0001, Some(me));
+        let mut ws = raw(10, "workspace");
+        ws["nodes"] = json!([win]);
+
+        let mut apps = Vec::new();
+        serialize_workspace(&node(ws), "dev", &mut apps);
+        assert!(!apps[0].command.is_empty());
+        assert_eq!(apps[0].command, proc_cmdline(Some(me)));
+    }
+
+    #[test]
+    fn only_the_target_workspace_is_adopted_from() {
+        let apps = vec![App {
+            mark: "_i3h_layout_dev_0".into(),
+            class: "kitty".into(),
+            instance: None,
+            command: vec![],
+        }];
+        let mut placeholder = raw(5, "con");
+        placeholder["marks"] = json!(["_i3h_layout_dev_0"]);
+        let mut ws1 = raw(10, "workspace");
+        ws1["name"] = "1".into();
+        ws1["nodes"] = json!([x11(1, "kitty", "kitty", "here")]);
+        let mut ws2 = raw(11, "workspace");
+        ws2["name"] = "2".into();
+        ws2["nodes"] = json!([x11(2, "kitty", "kitty", "there"), placeholder]);
+        let mut output = raw(1001, "output");
+        output["nodes"] = json!([ws1, ws2]);
+        let mut root = raw(1000, "root");
+        root["nodes"] = json!([output]);
+        let tree = node(root);
+
+        let ids = |v: Vec<&Node>| v.iter().map(|l| l.id).collect::<Vec<_>>();
+        assert_eq!(ids(candidates(&tree, &apps, false)), [2]);
+        assert_eq!(ids(candidates(&tree, &apps, true)), [1, 2]);
+    }
+
+    #[test]
+    fn command_arguments_are_quoted() {
+        assert_eq!(quote("/home/me/my layouts/dev.json"), r#""/home/me/my layouts/dev.json""#);
+        assert_eq!(quote(r#"a"b\c"#), r#""a\"b\\c""#);
+    }
+}
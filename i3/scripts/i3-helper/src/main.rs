@@ -1,43 +1,138 @@
 //! i3-helper: High-performance i3wm event daemon
 //!
-//! Replaces: alternating_layouts.py + workspace-names.py
+//! Replaces: alternating_layouts.py + workspace-names.py + sway_autotiling.py
+//! Runs under i3 and Sway (see `wm`).
 //! Single IPC connection, single process, <1ms per-event latency.
 //!
 //! Usage:
 //!   i3-helper                     # Start (default: alternating mode)
-//!   i3-helper --mode vertical     # Start with vertical tiling
-//!   pkill -SIGUSR1 i3-helper      # Cycle: alt → vert → horiz → alt
+//!   i3-helper --mode vertical     # Vertical default unless a saved state exists
+//!   i3-helper --reset-state       # Ignore modes saved by the last run (see `state`)
+//!   i3-helper -s v                # Vertical on the focused workspace only
+//!   i3-helper msg cycle-mode      # Control running daemon (see `ctl`)
+//!   i3-helper status -f --format waybar   # Bar feed, one line per change (see `status`)
+//!   i3-helper layout save dev     # Workspace → append_layout file (see `layout`)
+//!   i3-helper session restore     # Relaunch the last session's apps (see `session`)
+//!   i3-helper switch              # dmenu/rofi window switcher (see `switch`)
+//!   i3-helper mark goto f         # Marks that survive restarts (see `marks`)
+//!   i3-helper scratch term        # Named scratchpad: start, show or hide (see `scratch`)
+//!   pkill -SIGUSR1 i3-helper      # Cycle: alt → vert → horiz → master → spiral → alt
 //!   pkill -SIGUSR2 i3-helper      # Force refresh workspace names
-
-use anyhow::{Context, Result};
-use signal_hook::consts::{SIGUSR1, SIGUSR2};
+//!   pkill i3-helper               # Clean shutdown (removes runtime files)
+
+mod autotab;
+mod config;
+mod ctl;
+mod dynamic;
+mod instance;
+mod layout;
+mod marks;
+mod master;
+mod mru;
+mod rules;
+mod scratch;
+mod session;
+mod spiral;
+mod state;
+mod status;
+mod swallow;
+mod switch;
+#[cfg(test)]
+mod testutil;
+mod tiling;
+mod wm;
+
+use anyhow::{bail, Context, Result};
+use config::{Config, NameMode};
+use tiling::Tiler;
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
 use signal_hook::iterator::Signals;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fs;
 use std::process::Command as Cmd;
-use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use std::{env, thread};
-use swayipc::{Connection, Event, EventType, Node, NodeLayout, NodeType, WindowChange};
+use swayipc::{Connection, Event, EventType, Node, NodeType, WindowChange, WorkspaceChange};
 
 // ── Tiling Modes ──────────────────────────────────────────────
 
 const MODE_ALT: u8 = 0;
 const MODE_VERT: u8 = 1;
 const MODE_HORIZ: u8 = 2;
+const MODE_MASTER: u8 = 3;
+const MODE_SPIRAL: u8 = 4;
+const MODE_COUNT: u8 = 5;
+
+/// Tiling modes: a global default plus per-workspace overrides.
+///
+/// Keyed by `ws_key` (the workspace number when it has one) rather than by
+/// name, since names change on every icon update.
+struct Modes {
+    default: u8,
+    per_ws: BTreeMap<String, u8>,
+    /// Master-area size overrides (master mode); config default otherwise.
+    nmaster: BTreeMap<String, usize>,
+    /// `ws_key` of the focused workspace, tracked from the event loop so the
+    /// signal and control threads can target it without an IPC round-trip.
+    focused: String,
+}
+
+impl Modes {
+    #[inline]
+    fn get(&self, ws: &str) -> u8 {
+        self.per_ws.get(ws).copied().unwrap_or(self.default)
+    }
+
+    #[inline]
+    fn focused_mode(&self) -> u8 {
+        self.get(&self.focused)
+    }
+
+    #[inline]
+    fn nmaster(&self, ws: &str, default: usize) -> usize {
+        self.nmaster.get(ws).copied().unwrap_or(default)
+    }
+
+    /// Carry a workspace's overrides over to its new key after a renumber.
+    fn rekey(&mut self, old: &str, new: &str) {
+        if let Some(mode) = self.per_ws.remove(old) {
+            self.per_ws.insert(new.to_string(), mode);
+        }
+        if let Some(n) = self.nmaster.remove(old) {
+            self.nmaster.insert(new.to_string(), n);
+        }
+        if self.focused == old {
+            self.focused = new.to_string();
+        }
+    }
+}
 
-static TILING_MODE: AtomicU8 = AtomicU8::new(MODE_ALT);
+static MODES: Mutex<Modes> = Mutex::new(Modes {
+    default: MODE_ALT,
+    per_ws: BTreeMap::new(),
+    nmaster: BTreeMap::new(),
+    focused: String::new(),
+});
 static FORCE_REFRESH: AtomicBool = AtomicBool::new(false);
 
-/// Runtime dir for PID/mode files.
+/// Runtime dir for the mode file (read by status bars).
 /// `$XDG_RUNTIME_DIR` (portable, per-user, tmpfs on systemd) with `/tmp` fallback.
 #[inline]
 fn runtime_dir() -> String {
     env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".into())
 }
 
-#[inline]
-fn pid_path() -> String {
-    format!("{}/i3-helper.pid", runtime_dir())
+/// 0700 dir for the lock, control socket and request file; a per-uid name
+/// when falling back to the shared `/tmp` (see `instance`).
+fn private_dir() -> String {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => format!("{dir}/i3-helper"),
+        // SAFETY: getuid(2) cannot fail.
+        Err(_) => format!("/tmp/i3-helper-{}", unsafe { libc::getuid() }),
+    }
 }
 
 #[inline]
@@ -47,7 +142,12 @@ fn mode_path() -> String {
 
 #[inline]
 fn request_path() -> String {
-    format!("{}/i3-helper.request", runtime_dir())
+    format!("{}/request", private_dir())
+}
+
+#[inline]
+fn socket_path() -> String {
+    format!("{}/i3-helper.sock", private_dir())
 }
 
 #[inline]
@@ -56,6 +156,8 @@ fn parse_mode(s: &str) -> Option<u8> {
         "a" | "alternating" => Some(MODE_ALT),
         "v" | "vertical" => Some(MODE_VERT),
         "h" | "horizontal" => Some(MODE_HORIZ),
+        "m" | "master" => Some(MODE_MASTER),
+        "s" | "spiral" => Some(MODE_SPIRAL),
         _ => None,
     }
 }
@@ -66,92 +168,31 @@ fn mode_label(m: u8) -> &'static str {
         MODE_ALT => "alternating",
         MODE_VERT => "vertical",
         MODE_HORIZ => "horizontal",
+        MODE_MASTER => "master",
+        MODE_SPIRAL => "spiral",
         _ => "unknown",
     }
 }
 
+/// Lock the mode table. A panic while holding it can't leave it half-updated
+/// (every write is a single insert/store), so poisoning is ignored.
+#[inline]
+fn modes() -> MutexGuard<'static, Modes> {
+    MODES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 #[inline]
 fn mode_icon(m: u8) -> &'static str {
     match m {
         MODE_ALT => "⇔ Alternating",
         MODE_VERT => "↕ Vertical",
         MODE_HORIZ => "↔ Horizontal",
+        MODE_MASTER => "◧ Master",
+        MODE_SPIRAL => "↻ Spiral",
         _ => "? Unknown",
     }
 }
 
-// ── App Icon Map ──────────────────────────────────────────────
-// Pango markup for i3bar workspace rendering.
-// Each value is a full <span> tag matching the Python version.
-
-fn build_icon_map() -> HashMap<&'static str, &'static str> {
-    HashMap::from([
-        // Browsers
-        ("firefox",        "<span size='x-large'>\u{E658} </span>"),
-        ("google-chrome",  "<span size='x-large'>\u{F268} </span>"),
-        // Terminals
-        ("alacritty",      "<span size='x-large'>\u{EBCA} </span>"),
-        ("kitty",          "<span size='x-large'>\u{EBCA} </span>"),
-        ("st",             "<span size='x-large'>\u{EBCA} </span>"),
-        // Editors
-        ("code",           "<span size='x-large'>\u{F0A1E} </span>"),
-        ("code-oss",       "<span size='x-large'>\u{F0A1E} </span>"),
-        ("antigravity",    "<span size='x-large'>\u{E887} </span>"),
-        ("nvim",           "<span size='x-large'>\u{F36F} </span>"),
-        ("vim",            "<span size='x-large'>\u{E7C5} </span>"),
-        ("neovide",        "<span size='x-large'>\u{F36F} </span>"),
-        ("emacs",          "<span size='x-large'>\u{E632} </span>"),
-        // Development
-        ("jetbrains-idea",      "<span size='x-large'>\u{E7B5} </span>"),
-        ("jetbrains-clion",     "<span size='x-large'>\u{E61D} </span>"),
-        ("jetbrains-pycharm",   "<span size='x-large'>\u{E73C} </span>"),
-        ("jetbrains-webstorm",  "<span size='x-large'>\u{F06E6} </span>"),
-        ("jetbrains-rider",     "<span size='x-large'>\u{F01A7} </span>"),
-        ("jetbrains-goland",    "<span size='x-large'>\u{E627} </span>"),
-        ("jetbrains-datagrip",  "<span size='x-large'>\u{F1C0} </span>"),
-        ("jetbrains-rubymine",  "<span size='x-large'>\u{E739} </span>"),
-        ("jetbrains-phpstorm",  "<span size='x-large'>\u{E608} </span>"),
-        ("postman",        "<span size='x-large'>\u{F06EE} </span>"),
-        ("docker",         "<span size='x-large'>\u{F21F} </span>"),
-        ("virt-manager",   "<span size='x-large'>\u{F0894} </span>"),
-        ("gnome-boxes",    "<span size='x-large'>\u{F0894} </span>"),
-        ("virtualbox",     "<span size='x-large'>\u{F0894} </span>"),
-        // Communication
-        ("telegramdesktop","<span size='x-large'>\u{F2C6} </span>"),
-        ("telegram",       "<span size='x-large'>\u{F2C6} </span>"),
-        ("evolution",      "<span size='x-large'>\u{F01F0} </span>"),
-        // Media
-        ("pavucontrol",    "<span size='x-large'>\u{F057E} </span>"),
-        ("vlc",            "<span size='x-large'>\u{F057C} </span>"),
-        ("mpv",            "<span size='x-large'>\u{F36E} </span>"),
-        ("obs",            "<span size='x-large'>\u{F044B} </span>"),
-        ("obs-studio",     "<span size='x-large'>\u{F044B} </span>"),
-        ("gimp",           "<span size='x-large'>\u{F338} </span>"),
-        ("inkscape",       "<span size='x-large'>\u{F33B} </span>"),
-        ("steam",          "<span size='x-large'>\u{F1B6} </span>"),
-        // System & Utilities
-        ("thunar",         "<span size='x-large'>\u{F07B} </span>"),
-        ("yazi",           "<span size='x-large'>\u{F07B} </span>"),
-        ("htop",           "<span size='x-large'>\u{F04C5} </span>"),
-        ("btop",           "<span size='x-large'>\u{F04C5} </span>"),
-        ("gparted",        "<span size='x-large'>\u{F02CA} </span>"),
-        ("clock",          "<span size='x-large'>\u{F017} </span>"),
-        ("peaclock",       "<span size='x-large'>\u{F017} </span>"),
-        ("calc",           "<span size='x-large'>\u{F1EC} </span>"),
-        ("calculator",     "<span size='x-large'>\u{F1EC} </span>"),
-        ("galculator",     "<span size='x-large'>\u{F1EC} </span>"),
-        ("zathura",        "<span size='x-large'>\u{F1C1} </span>"),
-        // Custom
-        ("scratchpad",     "<span size='large'>\u{F0633} </span>"),
-        ("main-tmux",      "<span size='x-large'>\u{EBC8} </span>"),
-        ("gemini",         "<span size='x-large'>\u{F06A9} </span>"),
-        ("gemini-sc",      "<span size='x-large'>\u{F06A9} </span>"),
-        ("task",           "<span size='x-large'>\u{F0AE} </span>"),
-        ("tasks",          "<span size='x-large'>\u{F0AE} </span>"),
-        ("todo",           "<span size='x-large'>\u{F0AE} </span>"),
-    ])
-}
-
 // ── Tree Traversal Utilities ──────────────────────────────────
 
 /// Find focused container using i3's `focus` array for O(depth) guided
@@ -171,24 +212,56 @@ fn find_focused(node: &Node) -> Option<&Node> {
     None
 }
 
-/// Find the tiling parent of a window by searching ONLY through `nodes`.
-///
-/// Deliberately excludes `floating_nodes`. When a floating window
-/// (scratchpad, dialog, etc.) is focused, this returns `None`, which
-/// causes `handle_tiling` to skip — no special-casing needed.
-#[inline]
-fn find_tiling_parent(root: &Node, target_id: i64) -> Option<&Node> {
-    for child in &root.nodes {
-        if child.id == target_id {
-            return Some(root);
-        }
-        if let Some(found) = find_tiling_parent(child, target_id) {
-            return Some(found);
+/// Find the focused workspace by following `focus` arrays from the root.
+/// Unlike `find_focused`, this also works when the workspace is empty.
+fn focused_workspace(node: &Node) -> Option<&Node> {
+    if node.node_type == NodeType::Workspace {
+        return Some(node);
+    }
+    let &next_id = node.focus.first()?;
+    node.nodes
+        .iter()
+        .chain(node.floating_nodes.iter())
+        .find(|c| c.id == next_id)
+        .and_then(focused_workspace)
+}
+
+/// Find the workspace that (transitively) contains container `target_id`.
+fn workspace_of(node: &Node, target_id: i64) -> Option<&Node> {
+    fn contains(node: &Node, id: i64) -> bool {
+        node.id == id
+            || node.nodes.iter().chain(node.floating_nodes.iter()).any(|c| contains(c, id))
+    }
+    for child in &node.nodes {
+        if child.node_type == NodeType::Workspace {
+            if contains(child, target_id) {
+                return Some(child);
+            }
+        } else if let Some(ws) = workspace_of(child, target_id) {
+            return Some(ws);
         }
     }
     None
 }
 
+/// Find a workspace by `ws_key`.
+fn find_workspace<'a>(node: &'a Node, key: &str) -> Option<&'a Node> {
+    if node.node_type == NodeType::Workspace {
+        return (ws_key(node) == key).then_some(node);
+    }
+    node.nodes.iter().find_map(|c| find_workspace(c, key))
+}
+
+/// Stable key for per-workspace state: the number, or the name for
+/// named workspaces without one.
+#[inline]
+fn ws_key(ws: &Node) -> String {
+    match ws.num {
+        Some(n) if n >= 0 => n.to_string(),
+        _ => ws.name.clone().unwrap_or_default(),
+    }
+}
+
 /// Returns true if a workspace is i3's internal scratchpad.
 /// `num == -1` is canonical + immutable. Name is a secondary guard.
 #[inline]
@@ -220,11 +293,26 @@ fn collect_leaves<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
     }
 }
 
+/// Like `collect_leaves`, but tiling windows only (skips `floating_nodes`).
+fn collect_tiling_leaves<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
+    if node.nodes.is_empty() {
+        if node.node_type == NodeType::Con {
+            out.push(node);
+        }
+        return;
+    }
+    for child in &node.nodes {
+        collect_tiling_leaves(child, out);
+    }
+}
+
 #[inline]
 fn window_class(node: &Node) -> Option<String> {
     node.window_properties
         .as_ref()
         .and_then(|wp| wp.class.as_ref().or(wp.instance.as_ref()))
+        // Native Wayland clients under Sway have no X11 properties
+        .or(node.app_id.as_ref())
         .map(|s| {
             // Take last token (handles multi-word classes) and lowercase
             s.split_whitespace()
@@ -234,68 +322,153 @@ fn window_class(node: &Node) -> Option<String> {
         })
 }
 
-// ── Tiling Handler ────────────────────────────────────────────
-// Called on every Window::Focus event. O(n) tree traversal.
-//
-// Key insight: `find_tiling_parent` only walks `nodes` (not `floating_nodes`).
-// If the focused window is floating (scratchpad, dialog, etc.), the parent
-// search returns None and we skip. No special-casing needed.
+/// X11 window → `_NET_WM_PID`, looked up once per window. Pruned against
+/// every fresh tree so a recycled window id isn't given a stale pid.
+static X11_PIDS: Mutex<BTreeMap<i64, Option<i32>>> = Mutex::new(BTreeMap::new());
+
+/// Process owning a window. Sway reports it in the tree; i3 never does, so
+/// X11 windows fall back to their `_NET_WM_PID` property.
+fn window_pid(node: &Node) -> Option<i32> {
+    if node.pid.is_some() {
+        return node.pid;
+    }
+    let window = node.window?;
+    let mut pids = X11_PIDS.lock().unwrap_or_else(|e| e.into_inner());
+    *pids.entry(window).or_insert_with(|| {
+        let out = Cmd::new("xprop").args(["-id", &window.to_string(), "_NET_WM_PID"]).output().ok()?;
+        parse_wm_pid(&String::from_utf8_lossy(&out.stdout))
+    })
+}
+
+/// `_NET_WM_PID(CARDINAL) = 1234` → 1234; `not found.` → None.
+fn parse_wm_pid(xprop: &str) -> Option<i32> {
+    xprop.split_once('=')?.1.trim().parse().ok()
+}
+
+/// Forget the pids of windows that are no longer in `tree`.
+fn prune_pids(tree: &Node) {
+    let mut leaves = Vec::new();
+    collect_leaves(tree, &mut leaves);
+    let mut pids = X11_PIDS.lock().unwrap_or_else(|e| e.into_inner());
+    pids.retain(|w, _| leaves.iter().any(|l| l.window == Some(*w)));
+}
+
+/// Command line of process `pid` (`/proc/<pid>/cmdline`), empty if unknown.
+fn proc_cmdline(pid: Option<i32>) -> Vec<String> {
+    let raw = match pid.and_then(|p| fs::read(format!("/proc/{p}/cmdline")).ok()) {
+        Some(r) => r,
+        None => return Vec::new(),
+    };
+    raw.split(|&b| b == 0)
+        .filter(|a| !a.is_empty())
+        .map(|a| String::from_utf8_lossy(a).into_owned())
+        .collect()
+}
+
+// ── Placement ─────────────────────────────────────────────────
+// Called on Window::New / Window::Close only; focus changes cost no IPC.
 
-fn handle_tiling(cmd: &mut Connection, tree: &Node) -> Result<()> {
-    let focused = match find_focused(tree) {
-        Some(f) => f,
+/// Split fix-up for the alternating/vertical/horizontal modes.
+fn handle_tiling(cmd: &mut impl tiling::Commands, tiler: &mut Tiler, tree: &Node, con_id: i64) -> Result<()> {
+    let mode = match workspace_of(tree, con_id) {
+        Some(ws) => modes().get(&ws_key(ws)),
         None => return Ok(()),
     };
+    tiler.on_new(cmd, tree, con_id, mode)
+}
 
-    // find_tiling_parent returns None for floating windows (scratchpad, dialogs)
-    // because it only traverses `nodes`, not `floating_nodes`.
-    let parent = match find_tiling_parent(tree, focused.id) {
-        Some(p) => p,
+/// Placement for layouts that move windows rather than pre-set a split:
+/// master mode puts new windows into the master area or stack and reflows
+/// after a close; spiral mode splits the most recently created window.
+fn handle_placement(cmd: &mut Connection, tree: &Node, change: WindowChange, con_id: i64) -> Result<()> {
+    // A closed window is already gone from the tree; closes happen on the
+    // focused workspace in practice.
+    let ws = match change {
+        WindowChange::New => workspace_of(tree, con_id),
+        _ => focused_workspace(tree),
+    };
+    let ws = match ws {
+        Some(ws) => ws,
         None => return Ok(()),
     };
+    let key = ws_key(ws);
+    let cfg = config::get();
+    let (mode, nmaster) = {
+        let m = modes();
+        (m.get(&key), m.nmaster(&key, cfg.master.count))
+    };
 
-    // Skip tabbed/stacked — user chose that layout deliberately
-    if matches!(parent.layout, NodeLayout::Tabbed | NodeLayout::Stacked) {
-        return Ok(());
+    match (mode, change) {
+        (MODE_MASTER, WindowChange::New) => master::place(cmd, ws, con_id, nmaster, cfg.master.fraction),
+        (MODE_MASTER, _) => master::reflow(cmd, &key, nmaster, cfg.master.fraction),
+        (MODE_SPIRAL, WindowChange::New) => spiral::place(cmd, ws, &key, con_id, cfg.spiral.rotate),
+        _ => Ok(()),
     }
+}
 
-    let mode = TILING_MODE.load(Ordering::Relaxed);
+// ── Workspace Naming ──────────────────────────────────────────
+// Called on Window and Workspace events. O(w * l) where w=workspaces, l=avg leaves.
 
-    match mode {
-        MODE_ALT => {
-            // Alternating: split perpendicular to longest dimension
-            let r = &focused.rect;
-            if r.height > r.width {
-                if matches!(parent.layout, NodeLayout::SplitH) {
-                    cmd.run_command("split v")?;
-                }
-            } else if matches!(parent.layout, NodeLayout::SplitV) {
-                cmd.run_command("split h")?;
-            }
+/// Icon (or bare class when unmapped) for a single window, plus the key
+/// used to tell apps apart when deduplicating.
+fn leaf_icon<'a>(leaf: &Node, cfg: &'a Config) -> (String, Cow<'a, str>) {
+    let cls = rules::match_icon(&cfg.rules, leaf)
+        .map(str::to_string)
+        .or_else(|| window_class(leaf))
+        .or_else(|| leaf.name.clone())
+        .unwrap_or_default();
+
+    // Single lookup — avoids double hash
+    match cfg.icons.get(cls.as_str()) {
+        Some(icon) => (cls, Cow::Borrowed(icon.as_str())),
+        None => {
+            let text = Cow::Owned(cls.clone());
+            (cls, text)
         }
-        MODE_VERT => {
-            if !matches!(parent.layout, NodeLayout::SplitV) {
-                cmd.run_command("split v")?;
-            }
-        }
-        MODE_HORIZ => {
-            if !matches!(parent.layout, NodeLayout::SplitH) {
-                cmd.run_command("split h")?;
-            }
+    }
+}
+
+/// Render the icon part of a workspace label from its leaves (tree order).
+fn workspace_icons(leaves: &[&Node], cfg: &Config) -> Option<String> {
+    let first = leaves.first()?;
+    if cfg.names.mode == NameMode::First {
+        return Some(leaf_icon(first, cfg).1.into_owned());
+    }
+
+    // Distinct apps in first-seen order with window counts
+    let mut apps: Vec<(String, Cow<str>, usize)> = Vec::with_capacity(leaves.len());
+    for leaf in leaves {
+        let (key, icon) = leaf_icon(leaf, cfg);
+        match apps.iter_mut().find(|(k, _, _)| *k == key) {
+            Some(app) => app.2 += 1,
+            None => apps.push((key, icon, 1)),
         }
-        _ => {}
     }
 
-    Ok(())
+    let cap = match cfg.names.max_icons {
+        0 => apps.len(),
+        n => n.min(apps.len()),
+    };
+    let mut label = String::new();
+    for (_, icon, count) in &apps[..cap] {
+        label.push_str(icon);
+        if cfg.names.count && *count > 1 {
+            label.push_str(&format!("×{} ", count));
+        } else if !icon.ends_with("</span>") {
+            // Bare class names carry no span padding of their own
+            label.push(' ');
+        }
+    }
+    if cap < apps.len() {
+        label.push_str(&format!("+{}", apps.len() - cap));
+    }
+    Some(label.trim_end().to_string())
 }
 
-// ── Workspace Naming ──────────────────────────────────────────
-// Called on Window and Workspace events. O(w * l) where w=workspaces, l=avg leaves.
-
 fn update_workspace_names(
     cmd: &mut Connection,
     tree: &Node,
-    icons: &HashMap<&str, &str>,
+    cfg: &Config,
 ) -> Result<()> {
     let mut ws_buf = Vec::with_capacity(10);
     collect_workspaces(tree, &mut ws_buf);
@@ -319,14 +492,7 @@ fn update_workspace_names(
         leaf_buf.clear();
         collect_leaves(ws, &mut leaf_buf);
 
-        let new_label = if let Some(first) = leaf_buf.first() {
-            let cls = window_class(first)
-                .or_else(|| first.name.clone())
-                .unwrap_or_default();
-
-            // Single lookup — avoids double hash
-            let fallback: &str = icons.get(cls.as_str()).copied().unwrap_or(&cls);
-
+        let new_label = if let Some(fallback) = workspace_icons(&leaf_buf, cfg) {
             let ws_num_pos = ws_num.filter(|&n| n >= 0);
             match ws_num_pos {
                 Some(n) => format!("{}: {}", n, fallback),
@@ -363,6 +529,42 @@ fn update_workspace_names(
     Ok(())
 }
 
+/// Rename every numbered workspace back to its bare number ("3: 󰈹" → "3").
+/// Named workspaces without a number are left as they are.
+fn restore_names(cmd: &mut Connection, tree: &Node) -> Result<()> {
+    let mut ws_buf = Vec::with_capacity(10);
+    collect_workspaces(tree, &mut ws_buf);
+    let cmds: Vec<String> = ws_buf
+        .iter()
+        .filter_map(|ws| {
+            let n = ws.num.filter(|&n| n >= 0)?;
+            let name = ws.name.as_deref()?;
+            (name != n.to_string()).then(|| {
+                format!(r#"rename workspace "{}" to "{}""#, name.replace('"', "\\\""), n)
+            })
+        })
+        .collect();
+    if !cmds.is_empty() {
+        cmd.run_command(cmds.join("; "))?;
+    }
+    Ok(())
+}
+
+/// Fetch a fresh tree and re-render every workspace with the current config.
+fn refresh_names(cmd: &mut Connection) -> Result<()> {
+    let tree = cmd.get_tree().context("get_tree failed")?;
+    update_workspace_names(cmd, &tree, &config::get())
+}
+
+/// Open a side-channel connection on first use. Callers reset it to `None`
+/// after an IPC error so the next use reconnects instead of reusing a dead socket.
+fn ensure_conn(conn: &mut Option<Connection>) -> Result<&mut Connection> {
+    if conn.is_none() {
+        *conn = Some(wm::connect().context("Failed to connect to i3")?);
+    }
+    conn.as_mut().context("i3 connection unavailable")
+}
+
 // ── Process Management ────────────────────────────────────────
 
 /// If a previous buggy daemon run renamed `__i3_scratch`, restore it.
@@ -398,62 +600,126 @@ fn repair_scratchpad(cmd: &mut Connection, tree: &Node) {
     }
 }
 
-fn kill_previous() {
-    let path = pid_path();
-    if let Ok(pid_str) = fs::read_to_string(&path) {
-        if let Ok(pid) = pid_str.trim().parse::<i32>() {
-            if pid > 0 {
-                // SAFETY: kill(2) with sig=0 checks existence without signaling.
-                // PID validated > 0 to avoid killing process group 0.
-                unsafe {
-                    if libc::kill(pid, 0) == 0 {
-                        libc::kill(pid, libc::SIGTERM);
-                        thread::sleep(std::time::Duration::from_millis(50));
-                    }
-                }
+// ── Mode Switching ────────────────────────────────────────────
+// Shared by the SIGUSR1 handler and the control socket.
+
+/// Next mode in the cycle for the focused workspace.
+#[inline]
+fn next_mode() -> u8 {
+    (modes().focused_mode() + 1) % MODE_COUNT
+}
+
+/// Which workspaces a mode change applies to.
+enum Scope {
+    Focused,
+    /// The default for workspaces without their own mode.
+    Global,
+    Workspace(String),
+}
+
+/// Store `mode` for `scope`, publish the focused workspace's effective
+/// mode for the status bar and notify.
+fn apply_mode(mode: u8, scope: Scope) {
+    let (shown, title) = {
+        let mut m = modes();
+        let ws = match scope {
+            Scope::Focused if !m.focused.is_empty() => Some(m.focused.clone()),
+            // Focus not known yet (no event seen) → nothing to scope to
+            Scope::Focused | Scope::Global => None,
+            Scope::Workspace(ws) => Some(ws),
+        };
+        let title = match ws {
+            Some(ws) => {
+                let title = format!("Tiling Mode ({ws})");
+                m.per_ws.insert(ws, mode);
+                title
             }
-        }
-    }
+            None => {
+                m.default = mode;
+                "Tiling Mode (default)".to_string()
+            }
+        };
+        (m.focused_mode(), title)
+    };
+    let _ = fs::write(mode_path(), mode_label(shown));
+    state::save();
+    status::publish();
+    let _ = Cmd::new("notify-send")
+        .args(["-t", "1500", "-h", "string:x-canonical-private-synchronous:tiling", &title, mode_icon(mode)])
+        .spawn();
 }
 
-fn write_pid() -> Result<()> {
-    fs::write(pid_path(), std::process::id().to_string())
-        .context("Failed to write PID file")?;
-    Ok(())
+/// Remember the focused workspace; republish the mode file when it changes
+/// so the status bar always shows the mode of the workspace in front.
+fn track_focus(tree: &Node) {
+    let key = match focused_workspace(tree) {
+        Some(ws) => ws_key(ws),
+        None => return,
+    };
+    let mut m = modes();
+    if m.focused != key {
+        m.focused = key;
+        let _ = fs::write(mode_path(), mode_label(m.focused_mode()));
+    }
 }
 
 // ── Signal Setup ──────────────────────────────────────────────
 
+fn remove_runtime_files() {
+    let _ = fs::remove_file(mode_path());
+    let _ = fs::remove_file(socket_path());
+    let _ = fs::remove_file(request_path());
+}
+
+/// SIGTERM/SIGINT: leave nothing stale behind, then exit. Runs on the signal
+/// thread while the main thread may be blocked on the event stream, so it
+/// uses its own connection. The instance lock is released by the kernel on
+/// exit, after the cleanup — a replacing instance never races it.
+fn shutdown(sig: i32) -> ! {
+    eprintln!("i3-helper: signal {sig}, shutting down");
+    state::save();
+    if config::get().names.restore_on_exit {
+        let res = wm::connect().and_then(|mut c| {
+            let tree = c.get_tree().context("get_tree failed")?;
+            restore_names(&mut c, &tree)
+        });
+        if let Err(e) = res {
+            eprintln!("i3-helper: workspace names not restored: {e:#}");
+        }
+    }
+    remove_runtime_files();
+    std::process::exit(0);
+}
+
 fn setup_signals() -> Result<()> {
-    let mut signals =
-        Signals::new([SIGUSR1, SIGUSR2]).context("Failed to register signal handlers")?;
+    let mut signals = Signals::new([SIGUSR1, SIGUSR2, SIGTERM, SIGINT])
+        .context("Failed to register signal handlers")?;
 
     thread::spawn(move || {
         let req_file = request_path();
         for sig in signals.forever() {
             match sig {
                 SIGUSR1 => {
-                    // Check for explicit mode request (from --set-mode client)
-                    let next = if let Ok(req) = fs::read_to_string(&req_file) {
+                    // Check for explicit mode request (from --set-mode client):
+                    // "<mode>" or "<mode> global"
+                    let (next, scope) = if let Ok(req) = fs::read_to_string(&req_file) {
                         let _ = fs::remove_file(&req_file);
-                        parse_mode(req.trim()).unwrap_or_else(|| {
-                            (TILING_MODE.load(Ordering::Relaxed) + 1) % 3
-                        })
+                        let mut parts = req.split_whitespace();
+                        let mode = parts.next().and_then(parse_mode).unwrap_or_else(next_mode);
+                        match parts.next() {
+                            Some("global") => (mode, Scope::Global),
+                            _ => (mode, Scope::Focused),
+                        }
                     } else {
                         // No request file → cycle
-                        (TILING_MODE.load(Ordering::Relaxed) + 1) % 3
+                        (next_mode(), Scope::Focused)
                     };
-                    TILING_MODE.store(next, Ordering::Relaxed);
-                    let label = mode_label(next);
-                    let icon = mode_icon(next);
-                    let _ = fs::write(mode_path(), label);
-                    let _ = Cmd::new("notify-send")
-                        .args(["-t", "1500", "-h", "string:x-canonical-private-synchronous:tiling", "Tiling Mode", icon])
-                        .spawn();
+                    apply_mode(next, scope);
                 }
                 SIGUSR2 => {
                     FORCE_REFRESH.store(true, Ordering::Relaxed);
                 }
+                SIGTERM | SIGINT => shutdown(sig),
                 _ => {}
             }
         }
@@ -462,12 +728,147 @@ fn setup_signals() -> Result<()> {
     Ok(())
 }
 
+// ── Event Session ─────────────────────────────────────────────
+
+/// First reconnect delay; doubled per failed attempt up to `BACKOFF_MAX`.
+const BACKOFF_MIN: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+/// Give up once i3 has been unreachable this long (session ended; a new
+/// one starts its own instance via exec_always).
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Connect, resync and process events until a connection fails.
+///
+/// Socket discovery is redone on every call (`wm::connect`), since the path
+/// changes across i3 restarts. Returns the error that ended the session.
+fn session(tiler: &mut Tiler, backoff: &mut Duration, down_since: &mut Option<Instant>) -> Result<()> {
+    // Command connection (for get_tree + run_command)
+    let mut cmd_conn = wm::connect().context("Failed to connect to i3 (command channel)")?;
+    let wm = wm::detect(&mut cmd_conn);
+
+    // Event connection (subscribes to window + workspace events; blocking iterator)
+    let event_iter = wm::connect()
+        .context("Failed to connect to i3 (event channel)")?
+        .subscribe([EventType::Window, EventType::Workspace])
+        .context("Failed to subscribe to i3 events")?;
+
+    // Repair scratchpad if a previous buggy run renamed it, then full name
+    // sync: anything may have changed while we were disconnected
+    let tree = cmd_conn.get_tree().context("get_tree failed")?;
+    repair_scratchpad(&mut cmd_conn, &tree);
+    mru::prune(&tree);
+    track_focus(&tree);
+    status::update(&tree);
+    session::update(&tree);
+    update_workspace_names(&mut cmd_conn, &tree, &config::get())?;
+    log_err("swallow", swallow::refresh(&mut cmd_conn, &tree));
+    log_err("marks", marks::apply(&mut cmd_conn, &tree, None, &config::get().marks.auto));
+
+    let what = if down_since.take().is_some() { "reconnected" } else { "connected" };
+    eprintln!("i3-helper: {what} to {}", wm.name());
+    *backoff = BACKOFF_MIN;
+
+    for event in event_iter {
+        let event = event.context("event stream failed")?;
+
+        // Focus only updates the tiler's cache: no get_tree, no commands.
+        // A focus change to another workspace also emits a Workspace event.
+        let do_tree = match &event {
+            Event::Window(w) => {
+                tiler.observe(w.change, &w.container);
+                match w.change {
+                    WindowChange::Focus => {
+                        mru::on_focus(w.container.id);
+                        scratch::on_focus(&w.container);
+                    }
+                    WindowChange::New => scratch::on_new(w.container.id),
+                    WindowChange::Close => {
+                        mru::on_close(w.container.id);
+                        scratch::on_close(w.container.id);
+                    }
+                    _ => {}
+                }
+                matches!(
+                    w.change,
+                    WindowChange::New
+                        | WindowChange::Close
+                        | WindowChange::Move
+                        | WindowChange::Title
+                        | WindowChange::Floating
+                )
+            }
+            Event::Workspace(ws) => {
+                if ws.change == WorkspaceChange::Focus {
+                    if let Some(cur) = &ws.current {
+                        scratch::on_workspace_focus(cur.id);
+                    }
+                }
+                true
+            }
+            _ => false,
+        };
+
+        let force = FORCE_REFRESH.swap(false, Ordering::Relaxed);
+        if !(do_tree || force) {
+            continue;
+        }
+
+        // Single get_tree() call serves all handlers. If it fails the
+        // command connection is gone: end the session and reconnect both.
+        let tree = cmd_conn.get_tree().context("get_tree failed")?;
+        track_focus(&tree);
+        prune_pids(&tree);
+        status::update(&tree);
+        session::update(&tree);
+        if let Event::Window(w) = &event {
+            if w.change == WindowChange::New {
+                log_err("tiling", handle_tiling(&mut cmd_conn, tiler, &tree, w.container.id));
+            }
+            if matches!(w.change, WindowChange::New | WindowChange::Close) {
+                log_err("placement", handle_placement(&mut cmd_conn, &tree, w.change, w.container.id));
+            }
+            if matches!(w.change, WindowChange::New | WindowChange::Close | WindowChange::Move) {
+                log_err("autotab", autotab::apply(&mut cmd_conn, &tree, &config::get().autotab));
+            }
+            if w.change == WindowChange::New {
+                let prev = tiler.focused();
+                let res = swallow::on_new(&mut cmd_conn, &tree, w.container.id, prev, &config::get().swallow);
+                log_err("swallow", res);
+            }
+            if matches!(w.change, WindowChange::New | WindowChange::Title) {
+                let res = marks::apply(&mut cmd_conn, &tree, Some(w.container.id), &config::get().marks.auto);
+                log_err("marks", res);
+            }
+        }
+        log_err("swallow", swallow::refresh(&mut cmd_conn, &tree));
+        if config::get().workspaces.dynamic {
+            log_err("renumber", dynamic::renumber(&mut cmd_conn, &tree).map(drop));
+        }
+        log_err("naming", update_workspace_names(&mut cmd_conn, &tree, &config::get()));
+    }
+
+    bail!("event stream closed")
+}
+
+/// Handler errors don't end the session; a dead connection shows up on the
+/// next `get_tree`.
+#[inline]
+fn log_err(what: &str, res: Result<()>) {
+    if let Err(e) = res {
+        eprintln!("i3-helper: {what}: {e:#}");
+    }
+}
+
 // ── Main ──────────────────────────────────────────────────────
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     let mut initial_mode = MODE_ALT;
     let mut set_mode_request: Option<String> = None;
+    let mut set_global = false;
+    let mut reset_state = false;
+    let mut policy = instance::Policy::Replace;
     let mut i = 1;
     while i < args.len() {
         match args[i].as_str() {
@@ -476,10 +877,14 @@ fn main() -> Result<()> {
                 if i < args.len() {
                     set_mode_request = Some(args[i].clone());
                 } else {
-                    eprintln!("--set-mode requires a value: alternating|vertical|horizontal");
+                    eprintln!("--set-mode requires a value: alternating|vertical|horizontal|master|spiral");
                     std::process::exit(1);
                 }
             }
+            "--global" | "-g" => set_global = true,
+            "--reset-state" => reset_state = true,
+            "--replace" => policy = instance::Policy::Replace,
+            "--no-replace" => policy = instance::Policy::NoReplace,
             "--mode" | "-m" => {
                 i += 1;
                 if i < args.len() {
@@ -489,23 +894,83 @@ fn main() -> Result<()> {
                     });
                 }
             }
+            "msg" if i == 1 => {
+                return ctl::run_client(&args[2..]);
+            }
+            "status" if i == 1 => {
+                return status::run_client(&args[2..]);
+            }
+            "layout" if i == 1 => {
+                return layout::run_client(&args[2..]);
+            }
+            "session" if i == 1 => {
+                return session::run_client(&args[2..]);
+            }
+            "switch" if i == 1 => {
+                return switch::run_client(&args[2..]);
+            }
+            "mark" if i == 1 => {
+                return marks::run_client(&args[2..]);
+            }
+            "scratch" if i == 1 => {
+                return scratch::run_client(&args[2..]);
+            }
             "--help" | "-h" => {
                 println!("i3-helper: high-performance i3wm event daemon");
                 println!();
                 println!("Usage:");
                 println!("  i3-helper [OPTIONS]              Start daemon");
                 println!("  i3-helper --set-mode <MODE>      Set mode on running daemon");
+                println!("  i3-helper msg <COMMAND> [ARG]    Send command over control socket");
+                println!("  i3-helper status [-f|--follow] [--format json|i3blocks|waybar]");
+                println!("                                   Print bar status (on every change with -f)");
+                println!("  i3-helper layout save <NAME> [--workspace N]");
+                println!("  i3-helper layout restore <NAME> [--workspace N] [--launch] [--adopt-all]");
+                println!("  i3-helper layout list            Saved workspace layouts (i3 only)");
+                println!("  i3-helper session save|restore   Snapshot windows / relaunch them after login");
+                println!("  i3-helper switch                 Pick a window from a menu (MRU order)");
+                println!("  i3-helper mark set [MARK] [--title REGEX]");
+                println!("  i3-helper mark goto|swap|unset [MARK]");
+                println!("  i3-helper mark list              Marks that come back with their windows");
+                println!("  i3-helper scratch <NAME>         Start, show or hide a [scratchpads.NAME] app");
                 println!();
                 println!("Options:");
-                println!("  -m, --mode <MODE>      Initial tiling mode");
-                println!("  -s, --set-mode <MODE>  Signal running daemon to switch mode");
+                println!("  -m, --mode <MODE>      Default tiling mode when no state is saved");
+                println!("      --reset-state      Discard saved modes and start from --mode");
+                println!("      --replace          Stop a running instance and take over (default)");
+                println!("      --no-replace       Exit quietly if an instance is already running");
+                println!("  -s, --set-mode <MODE>  Switch mode of the focused workspace");
+                println!("  -g, --global           With --set-mode: change the default instead");
                 println!("  -h, --help             Show this help");
                 println!();
-                println!("Modes: alternating (a) | vertical (v) | horizontal (h)");
+                println!("Modes: alternating (a) | vertical (v) | horizontal (h) | master (m) | spiral (s)");
+                println!();
+                println!("Commands (msg):");
+                println!("  set-mode <MODE> [--global | --workspace <WS>]");
+                println!("                    Switch mode (default: focused workspace)");
+                println!("  cycle-mode        Cycle the focused workspace's tiling mode");
+                println!("  master-promote    Swap focused window with the master (master mode)");
+                println!("  master-count <N|+N|-N>");
+                println!("                    Set windows in the master area (focused workspace)");
+                println!("  refresh-names     Re-render workspace names now");
+                println!("  reload-config     Re-read config.toml (also automatic on save)");
+                println!("  get-status        Print daemon status as JSON");
+                println!("  next-empty        Focus the first free workspace on this output");
+                println!("  move-to-next-empty");
+                println!("                    Take the focused window there");
+                println!("  renumber          Close gaps in workspace numbers (see workspaces.dynamic)");
+                println!("  focus-last        Focus the previously focused window (any workspace)");
+                println!("  focus-prev [N]    Focus the window N steps back in focus history");
+                println!("  list-mru          Print focus history as JSON");
+                println!("  mark-set <MARK> [TITLE-REGEX]");
+                println!("                    Mark the focused window and remember it");
+                println!("  mark-unset [MARK] Forget a mark (default: the focused window's)");
+                println!("  list-marks        Print marks and their windows as JSON");
                 println!();
                 println!("Signals:");
-                println!("  SIGUSR1  Cycle tiling mode (alt → vert → horiz → alt)");
+                println!("  SIGUSR1  Cycle focused workspace mode (alt → vert → horiz → master → spiral → alt)");
                 println!("  SIGUSR2  Force refresh workspace names");
+                println!("  SIGTERM  Clean shutdown (SIGINT too); see names.restore_on_exit");
                 return Ok(());
             }
             _ => {}
@@ -513,119 +978,114 @@ fn main() -> Result<()> {
         i += 1;
     }
 
-    // Client mode: signal running daemon to set a specific mode
+    // Client mode: ask running daemon to set a specific mode
     if let Some(mode_str) = set_mode_request {
         if parse_mode(&mode_str).is_none() {
-            eprintln!("Unknown mode '{}'. Use: alternating|vertical|horizontal", mode_str);
+            eprintln!("Unknown mode '{}'. Use: alternating|vertical|horizontal|master|spiral", mode_str);
             std::process::exit(1);
         }
-        // Write request file, then signal daemon
-        fs::write(request_path(), &mode_str)
+        // Preferred path: control socket (serialized, acknowledged)
+        let req = ctl::Request::SetMode { mode: mode_str.clone(), global: set_global, workspace: None };
+        match ctl::send(&req) {
+            Ok(reply) => return ctl::report(reply),
+            Err(e) => eprintln!("i3-helper: control socket unavailable ({e:#}), falling back to SIGUSR1"),
+        }
+        // Fallback: write request file, then signal daemon
+        let req = if set_global { format!("{mode_str} global") } else { mode_str };
+        fs::write(request_path(), req)
             .context("Failed to write mode request")?;
-        let pid_file = pid_path();
-        if let Ok(pid_str) = fs::read_to_string(&pid_file) {
-            if let Ok(pid) = pid_str.trim().parse::<i32>() {
-                if pid > 0 {
-                    unsafe { libc::kill(pid, libc::SIGUSR1) };
-                }
+        match instance::running_pid() {
+            // SAFETY: pid > 0 and belongs to the lock holder.
+            Some(pid) => unsafe {
+                libc::kill(pid, libc::SIGUSR1);
+            },
+            None => {
+                eprintln!("i3-helper daemon not running");
+                std::process::exit(1);
             }
-        } else {
-            eprintln!("i3-helper daemon not running (no PID file)");
-            std::process::exit(1);
         }
         return Ok(());
     }
 
-    // Ensure i3 socket is discoverable (swayipc checks I3SOCK → SWAYSOCK → i3 --get-socketpath)
-    if env::var("I3SOCK").is_err() && env::var("SWAYSOCK").is_err() {
-        if let Ok(output) = Cmd::new("i3").arg("--get-socketpath").output() {
-            if output.status.success() {
-                let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                // SAFETY: single-threaded at this point (before thread::spawn)
-                unsafe { env::set_var("I3SOCK", &path) };
-            }
+    // Take over from (or defer to) a running instance
+    let _guard = match instance::acquire(policy)? {
+        Some(g) => g,
+        None => return Ok(()),
+    };
+
+    // Initialize state: saved modes win over --mode, which only seeds a
+    // fresh state
+    if reset_state {
+        if let Err(e) = state::reset() {
+            eprintln!("i3-helper: {e:#}");
         }
     }
+    let default_mode = {
+        let mut m = modes();
+        m.default = initial_mode;
+        if let Err(e) = state::restore(&mut m) {
+            eprintln!("i3-helper: saved state ignored: {e:#}");
+        }
+        m.default
+    };
+    let _ = fs::write(mode_path(), mode_label(default_mode));
 
-    // Kill any previous instance
-    kill_previous();
-
-    // Initialize state
-    TILING_MODE.store(initial_mode, Ordering::Relaxed);
-    let _ = fs::write(mode_path(), mode_label(initial_mode));
-    write_pid()?;
-
-    // Signal handlers (SIGUSR1 = cycle mode, SIGUSR2 = force refresh)
+    // Signal handlers (SIGUSR1 = cycle mode, SIGUSR2 = force refresh,
+    // SIGTERM/SIGINT = clean shutdown)
     setup_signals()?;
 
-    let icons = build_icon_map();
-
-    // Command connection (for get_tree + run_command)
-    let mut cmd_conn = Connection::new().context("Failed to connect to i3 (command channel)")?;
-
-    // Repair scratchpad if a previous buggy run renamed it, then initial name sync
-    if let Ok(tree) = cmd_conn.get_tree() {
-        repair_scratchpad(&mut cmd_conn, &tree);
-    }
-    if let Ok(tree) = cmd_conn.get_tree() {
-        let _ = update_workspace_names(&mut cmd_conn, &tree, &icons);
+    // Control socket (acknowledged counterpart to the signals above)
+    if let Err(e) = ctl::spawn_server() {
+        eprintln!("i3-helper: control socket disabled: {e:#}");
     }
 
-    // Event connection (subscribes to window + workspace events; blocking iterator)
-    let event_iter = Connection::new()
-        .context("Failed to connect to i3 (event channel)")?
-        .subscribe([EventType::Window, EventType::Workspace])
-        .context("Failed to subscribe to i3 events")?;
+    // Periodic session snapshots for `session restore`; focus history
+    // reaches the state file shortly after it changes
+    session::spawn_writer();
+    mru::spawn_writer();
+
+    // Load config up front so a bad file is reported at startup, then
+    // re-apply it live on every save
+    let _ = config::get();
+    let mut watch_conn: Option<Connection> = None;
+    let watched = config::spawn_watcher(move || {
+        let res = ensure_conn(&mut watch_conn).and_then(refresh_names);
+        if let Err(e) = res {
+            eprintln!("i3-helper: refresh after reload failed: {e:#}");
+            watch_conn = None;
+        }
+    });
+    if let Err(e) = watched {
+        eprintln!("i3-helper: config hot-reload disabled: {e:#}");
+    }
 
     eprintln!(
         "i3-helper: started (pid={}, mode={})",
         std::process::id(),
-        mode_label(initial_mode)
+        mode_label(default_mode)
     );
 
-    for event in event_iter {
-        let event = match event {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("i3-helper: event error: {e}");
-                break; // Connection lost — i3 will restart us via exec_always
-            }
-        };
-
-        let (do_tiling, do_names) = match &event {
-            Event::Window(w) => (
-                w.change == WindowChange::Focus,
-                matches!(
-                    w.change,
-                    WindowChange::Focus
-                        | WindowChange::New
-                        | WindowChange::Close
-                        | WindowChange::Move
-                        | WindowChange::Title
-                ),
-            ),
-            Event::Workspace(_) => (false, true),
-            _ => (false, false),
-        };
-
-        let force = FORCE_REFRESH.swap(false, Ordering::Relaxed);
-
-        if do_tiling || do_names || force {
-            // Single get_tree() call serves both handlers
-            if let Ok(tree) = cmd_conn.get_tree() {
-                if do_tiling {
-                    let _ = handle_tiling(&mut cmd_conn, &tree);
-                }
-                if do_names || force {
-                    let _ = update_workspace_names(&mut cmd_conn, &tree, &icons);
-                }
-            }
+    // Supervise the IPC connections: every drop (i3 restart, socket hiccup)
+    // reconnects with backoff. Modes and per-container state live outside
+    // the session and survive it.
+    let mut tiler = Tiler::default();
+    let mut backoff = BACKOFF_MIN;
+    let mut down_since: Option<Instant> = None;
+    loop {
+        if let Err(e) = session(&mut tiler, &mut backoff, &mut down_since) {
+            eprintln!("i3-helper: {e:#}");
+        }
+        let since = *down_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= RECONNECT_TIMEOUT {
+            eprintln!("i3-helper: i3 unreachable for {}s, exiting", RECONNECT_TIMEOUT.as_secs());
+            break;
         }
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(BACKOFF_MAX);
     }
 
     // Cleanup on exit
-    let _ = fs::remove_file(pid_path());
-    let _ = fs::remove_file(mode_path());
+    remove_runtime_files();
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,477 @@
+//! Marks that outlive their windows.
+//!
+//! An i3 mark belongs to a container: restarting an app, or a restart that
+//! recreates its window, loses it. Here a mark is also remembered as
+//! criteria for the window that carried it (class and instance, optionally
+//! a title pattern) and put back on the first matching window whenever no
+//! window has it: on startup, and when a window appears or changes title.
+//!
+//! Marks set with `mark set` are kept in the state file until `mark unset`.
+//! Auto marks work the same without being set, so "f" is firefox from the
+//! moment it opens. Built in: a (antigravity), f (firefox), g (chrome) and
+//! t (thunar); `[[marks.auto]]` adds more or replaces one by its mark:
+//!
+//!   [[marks.auto]]
+//!   mark = "c"
+//!   class = "^code$"        # also instance, title, app_id (regexes)
+//!
+//!   i3-helper mark set f [--title RE]   # mark the focused window
+//!   i3-helper mark goto f               # focus the window marked f
+//!   i3-helper mark swap f               # swap the focused window with it
+//!   i3-helper mark unset [f]            # forget f (or the focused window's)
+//!   i3-helper mark list                 # mark, class — title
+//!
+//! Without a mark, set/goto/swap/unset ask through `marks.command`, a
+//! dmenu-style menu. Marks starting with `_` are i3's hidden marks and
+//! left alone.
+
+use crate::ctl::{self, Request};
+use crate::rules::{regex, Matcher};
+use crate::tiling::Commands;
+use crate::{config, find_focused, mru, state, switch, window_class, wm};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use swayipc::{Connection, Node};
+
+// ── Criteria ──────────────────────────────────────────────────
+
+/// Window a mark belongs to, as regexes (i3 criteria semantics).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Criteria {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Sway: Wayland app_id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+}
+
+impl Criteria {
+    /// The `rules` matcher for these criteria; also used by `scratch`.
+    pub fn compile(&self) -> Result<Matcher> {
+        let m = Matcher {
+            class: regex("class", self.class.as_deref())?,
+            instance: regex("instance", self.instance.as_deref())?,
+            title: regex("title", self.title.as_deref())?,
+            app_id: regex("app_id", self.app_id.as_deref())?,
+            ..Matcher::default()
+        };
+        if m.is_empty() {
+            bail!("needs at least one of class, instance, title, app_id");
+        }
+        Ok(m)
+    }
+}
+
+/// Criteria naming `node`'s app exactly; `title` is an optional pattern.
+fn criteria_of(node: &Node, title: Option<String>) -> Criteria {
+    let wp = node.window_properties.as_ref();
+    let exact = |s: &str| format!("^{}$", regex::escape(s));
+    Criteria {
+        class: wp.and_then(|p| p.class.as_deref()).map(exact),
+        instance: wp.and_then(|p| p.instance.as_deref()).map(exact),
+        title,
+        app_id: node.app_id.as_deref().map(exact),
+    }
+}
+
+fn check_name(mark: &str) -> Result<()> {
+    if mark.is_empty() || mark.starts_with('_') {
+        bail!("invalid mark '{mark}' (empty, or hidden: starts with '_')");
+    }
+    Ok(())
+}
+
+// ── Auto Marks (config) ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawAutoMark {
+    mark: String,
+    class: Option<String>,
+    instance: Option<String>,
+    title: Option<String>,
+    app_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct AutoMark {
+    pub mark: String,
+    matcher: Matcher,
+}
+
+/// Built-in auto marks: (mark, class), for the app launcher mode's apps.
+const BUILTIN_AUTO: &[(&str, &str)] = &[
+    ("a", "(?i)^antigravity$"),
+    ("f", "(?i)^firefox$"),
+    ("g", "(?i)^google-chrome$"),
+    ("t", "(?i)^thunar$"),
+];
+
+pub fn builtin_auto() -> Result<Vec<AutoMark>> {
+    BUILTIN_AUTO
+        .iter()
+        .map(|&(mark, class)| {
+            let c = Criteria { class: Some(class.into()), ..Criteria::default() };
+            let matcher = c.compile().with_context(|| format!("built-in auto mark '{mark}'"))?;
+            Ok(AutoMark { mark: mark.into(), matcher })
+        })
+        .collect()
+}
+
+/// Built-ins plus `[[marks.auto]]` entries, an entry replacing the
+/// built-in of the same mark.
+pub fn compile_auto(raw: Vec<RawAutoMark>) -> Result<Vec<AutoMark>> {
+    let mut auto = builtin_auto()?;
+    for (i, r) in raw.into_iter().enumerate() {
+        check_name(&r.mark).with_context(|| format!("marks.auto[{i}]"))?;
+        let c = Criteria { class: r.class, instance: r.instance, title: r.title, app_id: r.app_id };
+        let matcher = c.compile().with_context(|| format!("marks.auto[{i}]"))?;
+        auto.retain(|a| a.mark != r.mark);
+        auto.push(AutoMark { mark: r.mark, matcher });
+    }
+    Ok(auto)
+}
+
+// ── Saved Marks ───────────────────────────────────────────────
+
+struct Saved {
+    criteria: Criteria,
+    matcher: Matcher,
+}
+
+static SAVED: Mutex<BTreeMap<String, Saved>> = Mutex::new(BTreeMap::new());
+
+#[inline]
+fn saved() -> std::sync::MutexGuard<'static, BTreeMap<String, Saved>> {
+    SAVED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// For the state file.
+pub fn snapshot() -> BTreeMap<String, Criteria> {
+    saved().iter().map(|(m, s)| (m.clone(), s.criteria.clone())).collect()
+}
+
+/// From the state file. Entries that no longer compile are dropped.
+pub fn restore(marks: BTreeMap<String, Criteria>) {
+    let mut s = saved();
+    s.clear();
+    for (mark, criteria) in marks {
+        match criteria.compile() {
+            Ok(matcher) => {
+                s.insert(mark, Saved { criteria, matcher });
+            }
+            Err(e) => eprintln!("i3-helper: saved mark '{mark}' dropped: {e:#}"),
+        }
+    }
+}
+
+// ── Re-applying ───────────────────────────────────────────────
+
+fn quote(mark: &str) -> String {
+    format!("\"{}\"", mark.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn collect_marks<'a>(node: &'a Node, out: &mut BTreeSet<&'a str>) {
+    out.extend(node.marks.iter().map(String::as_str));
+    for c in node.nodes.iter().chain(node.floating_nodes.iter()) {
+        collect_marks(c, out);
+    }
+}
+
+/// Commands giving every missing mark (saved first, then auto) to the
+/// first of `candidates` that matches it.
+fn plan(tree: &Node, candidates: &[&Node], saved: &BTreeMap<String, Saved>, auto: &[AutoMark]) -> Vec<String> {
+    let mut taken = BTreeSet::new();
+    collect_marks(tree, &mut taken);
+    let wanted = saved
+        .iter()
+        .map(|(m, s)| (m.as_str(), &s.matcher))
+        .chain(auto.iter().map(|a| (a.mark.as_str(), &a.matcher)));
+    let mut cmds = Vec::new();
+    for (mark, matcher) in wanted {
+        if taken.contains(mark) {
+            continue;
+        }
+        if let Some(n) = candidates.iter().find(|n| matcher.matches(n)) {
+            cmds.push(format!("[con_id={}] mark --add {}", n.id, quote(mark)));
+            taken.insert(mark);
+        }
+    }
+    cmds
+}
+
+/// Put back marks no window has. `only` limits the candidates to one
+/// window (a new or retitled one); `None` tries every window.
+pub fn apply(cmd: &mut impl Commands, tree: &Node, only: Option<i64>, auto: &[AutoMark]) -> Result<()> {
+    let wins: Vec<&Node> = mru::windows(tree)
+        .into_iter()
+        .map(|(_, n)| n)
+        .filter(|n| only.is_none_or(|id| n.id == id))
+        .collect();
+    if wins.is_empty() {
+        return Ok(());
+    }
+    let cmds = plan(tree, &wins, &saved(), auto);
+    if cmds.is_empty() {
+        return Ok(());
+    }
+    cmd.run(&cmds.join("; "))
+}
+
+// ── Requests ──────────────────────────────────────────────────
+
+/// Mark the focused window and remember it.
+pub fn set(cmd: &mut Connection, mark: &str, title: Option<String>) -> Result<Criteria> {
+    check_name(mark)?;
+    let tree = cmd.get_tree().context("get_tree failed")?;
+    let focused = find_focused(&tree).context("no focused window")?;
+    let criteria = criteria_of(focused, title);
+    let matcher = criteria.compile()?;
+    for outcome in cmd.run_command(format!("[con_id={}] mark --add {}", focused.id, quote(mark)))? {
+        outcome.context("mark failed")?;
+    }
+    saved().insert(mark.to_string(), Saved { criteria: criteria.clone(), matcher });
+    state::save();
+    Ok(criteria)
+}
+
+/// Remove and forget `mark`, or every mark of the focused window.
+pub fn unset(cmd: &mut Connection, mark: Option<&str>) -> Result<Vec<String>> {
+    let marks = match mark {
+        Some(m) => vec![m.to_string()],
+        None => {
+            let tree = cmd.get_tree().context("get_tree failed")?;
+            let focused = find_focused(&tree).context("no focused window")?;
+            focused.marks.iter().filter(|m| !m.starts_with('_')).cloned().collect()
+        }
+    };
+    if marks.is_empty() {
+        return Ok(marks);
+    }
+    let c: Vec<String> = marks.iter().map(|m| format!("unmark {}", quote(m))).collect();
+    cmd.run_command(c.join("; "))?;
+    {
+        let mut s = saved();
+        for m in &marks {
+            s.remove(m);
+        }
+    }
+    state::save();
+    Ok(marks)
+}
+
+/// Every visible mark, saved or auto mark with the window holding it.
+pub fn list(cmd: &mut Connection) -> Result<Vec<Value>> {
+    let tree = cmd.get_tree().context("get_tree failed")?;
+    let wins = mru::windows(&tree);
+    let auto = &config::get().marks.auto;
+    let saved = saved();
+    let mut names: BTreeSet<&str> = saved.keys().map(String::as_str).collect();
+    names.extend(auto.iter().map(|a| a.mark.as_str()));
+    names.extend(wins.iter().flat_map(|(_, n)| n.marks.iter().map(String::as_str)));
+    Ok(names
+        .into_iter()
+        .filter(|m| !m.starts_with('_'))
+        .map(|m| {
+            let holder = wins.iter().map(|(_, n)| n).find(|n| n.marks.iter().any(|x| x == m));
+            json!({
+                "mark": m,
+                "id": holder.map(|n| n.id),
+                "class": holder.and_then(|n| window_class(n)),
+                "title": holder.and_then(|n| n.name.clone()),
+                "saved": saved.contains_key(m),
+                "auto": auto.iter().any(|a| a.mark == m),
+            })
+        })
+        .collect())
+}
+
+// ── Client ────────────────────────────────────────────────────
+
+/// (mark, window) from the daemon; straight from i3 if it isn't running.
+fn entries() -> Result<Vec<(String, Option<String>)>> {
+    if let Ok(reply) = ctl::send(&Request::ListMarks) {
+        if let Some(marks) = reply["marks"].as_array() {
+            return Ok(marks
+                .iter()
+                .filter_map(|m| {
+                    let name = m["mark"].as_str()?.to_string();
+                    let win = m["id"].as_i64().map(|_| {
+                        let class = m["class"].as_str().unwrap_or_default();
+                        format!("{class} — {}", m["title"].as_str().unwrap_or_default())
+                    });
+                    Some((name, win))
+                })
+                .collect());
+        }
+    }
+    let marks = wm::connect()?.get_marks().context("get_marks failed")?;
+    Ok(marks.into_iter().filter(|m| !m.starts_with('_')).map(|m| (m, Some(String::new()))).collect())
+}
+
+/// The mark in `args[0]`, or one picked from the menu (`None`: cancelled).
+fn mark_arg(args: &[String], open_only: bool) -> Result<Option<String>> {
+    if let Some(m) = args.first() {
+        return Ok(Some(m.clone()));
+    }
+    let input: String = match open_only {
+        // Free-form: type a new one
+        false => String::new(),
+        true => entries()?
+            .into_iter()
+            .filter_map(|(m, win)| Some(format!("{m}\t{}\n", win?)))
+            .collect(),
+    };
+    if open_only && input.is_empty() {
+        let _ = std::process::Command::new("notify-send").args(["-t", "1500", "Marks", "No marks set"]).status();
+        return Ok(None);
+    }
+    let choice = switch::choose(&config::get().marks.command, "mark", &input)?;
+    let mark = choice.split('\t').next().unwrap_or_default().trim();
+    Ok((!mark.is_empty()).then(|| mark.to_string()))
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: i3-helper mark set [MARK] [--title REGEX]");
+    eprintln!("       i3-helper mark goto|swap|unset [MARK]");
+    eprintln!("       i3-helper mark list");
+    std::process::exit(1);
+}
+
+/// `i3-helper mark <set|goto|swap|unset|list> [MARK]`
+pub fn run_client(args: &[String]) -> Result<()> {
+    let sub = args.first().map(String::as_str).unwrap_or_else(|| usage());
+    let rest = &args[1..];
+    match sub {
+        "set" => {
+            let (mut mark, mut title) = (None, None);
+            let mut i = 0;
+            while i < rest.len() {
+                match rest[i].as_str() {
+                    "--title" | "-t" => {
+                        i += 1;
+                        title = Some(rest.get(i).cloned().unwrap_or_else(|| usage()));
+                    }
+                    m if mark.is_none() => mark = Some(m.to_string()),
+                    _ => usage(),
+                }
+                i += 1;
+            }
+            let mark = match mark {
+                Some(m) => m,
+                None => match mark_arg(&[], false)? {
+                    Some(m) => m,
+                    None => return Ok(()),
+                },
+            };
+            ctl::report(ctl::send(&Request::MarkSet { mark, title })?)
+        }
+        "unset" => {
+            if rest.len() > 1 {
+                usage();
+            }
+            ctl::report(ctl::send(&Request::MarkUnset { mark: rest.first().cloned() })?)
+        }
+        "goto" | "swap" => {
+            if rest.len() > 1 {
+                usage();
+            }
+            let mark = match mark_arg(rest, true)? {
+                Some(m) => m,
+                None => return Ok(()),
+            };
+            let c = match sub {
+                "goto" => format!("[con_mark={}] focus", quote(&format!("^{}$", regex::escape(&mark)))),
+                _ => format!("swap container with mark {}", quote(&mark)),
+            };
+            for outcome in wm::connect()?.run_command(c)? {
+                outcome.with_context(|| format!("{sub} {mark} failed"))?;
+            }
+            Ok(())
+        }
+        "list" => {
+            for (mark, win) in entries()? {
+                println!("{mark}\t{}", win.as_deref().unwrap_or("(not open)"));
+            }
+            Ok(())
+        }
+        _ => usage(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{node, raw, window};
+    use serde_json::json;
+
+    fn auto(mark: &str, class: &str) -> AutoMark {
+        let c = Criteria { class: Some(class.into()), ..Default::default() };
+        AutoMark { mark: mark.into(), matcher: c.compile().unwrap() }
+    }
+
+    #[test]
+    fn recorded_criteria_match_the_app_not_the_window() {
+        let ff = window("firefox", "Navigator", "Mozilla Firefox");
+        let c = criteria_of(&ff, None);
+        assert_eq!(c.class.as_deref(), Some("^firefox$"));
+        let m = c.compile().unwrap();
+        assert!(m.matches(&window("firefox", "Navigator", "another page")));
+        assert!(!m.matches(&window("firefox-esr", "Navigator", "Mozilla Firefox")));
+        assert!(Criteria::default().compile().is_err());
+    }
+
+    #[test]
+    fn builtin_auto_marks_compile() {
+        let auto = builtin_auto().unwrap();
+        let marks: Vec<&str> = auto.iter().map(|a| a.mark.as_str()).collect();
+        assert_eq!(marks, ["a", "f", "g", "t"]);
+        assert!(auto[1].matcher.matches(&window("Firefox", "Navigator", "x")));
+    }
+
+    #[test]
+    fn config_entries_add_to_or_replace_builtins() {
+        let raw: Vec<RawAutoMark> = toml::from_str::<BTreeMap<String, Vec<RawAutoMark>>>(
+            "auto = [{ mark = \"c\", class = \"^code$\" }, { mark = \"f\", class = \"^librewolf$\" }]",
+        )
+        .unwrap()
+        .remove("auto")
+        .unwrap();
+        let auto = compile_auto(raw).unwrap();
+        let marks: Vec<&str> = auto.iter().map(|a| a.mark.as_str()).collect();
+        assert_eq!(marks, ["a", "g", "t", "c", "f"]);
+        let f = auto.iter().find(|a| a.mark == "f").unwrap();
+        assert!(f.matcher.matches(&window("librewolf", "Navigator", "x")));
+        assert!(!f.matcher.matches(&window("firefox", "Navigator", "x")));
+        // The built-in "t" still follows thunar
+        assert!(auto[2].matcher.matches(&window("Thunar", "thunar", "~")));
+    }
+
+    #[test]
+    fn missing_marks_go_to_the_first_match_once() {
+        let mut a = raw(1, "con");
+        a["window_properties"] = json!({ "class": "firefox", "instance": "Navigator" });
+        let mut b = raw(2, "con");
+        b["window_properties"] = json!({ "class": "firefox", "instance": "Navigator" });
+        let mut t = raw(3, "con");
+        t["window_properties"] = json!({ "class": "Thunar", "instance": "thunar" });
+        t["marks"] = json!(["t"]);
+        let mut ws = raw(10, "workspace");
+        ws["nodes"] = json!([a, b, t]);
+        let tree = node(ws);
+        let wins: Vec<&Node> = tree.nodes.iter().collect();
+
+        let rules = [auto("f", "^firefox$"), auto("t", "^Thunar$"), auto("w", "^firefox$")];
+        let cmds = plan(&tree, &wins, &BTreeMap::new(), &rules);
+        // "t" is already on a window; "f" and "w" both land on the first firefox
+        assert_eq!(cmds, ["[con_id=1] mark --add \"f\"", "[con_id=1] mark --add \"w\""]);
+    }
+}
@@ -0,0 +1,213 @@
+//! dwm-style master–stack layout.
+//!
+//!   ┌──────────┬───────┐
+//!   │          │  s1   │
+//!   │  master  ├───────┤
+//!   │          │  s2   │
+//!   └──────────┴───────┘
+//!
+//! The workspace is split horizontally into two columns: the first holds up
+//! to `nmaster` windows, the second is a vertical stack for the rest. New
+//! windows are moved into place on `WindowChange::New` via a temporary mark,
+//! so it doesn't matter where i3 originally attached them.
+
+use crate::collect_tiling_leaves;
+use anyhow::{Context, Result};
+use swayipc::{Connection, Node};
+
+/// Temporary mark used as a `move container to mark` target.
+const MARK: &str = "_i3h_master";
+
+/// Upper bound on moves per reflow; each move fixes one window, so this
+/// only trips if i3 refuses a move and the layout never converges.
+const MAX_REFLOW_STEPS: usize = 16;
+
+/// Master column, stack column and their windows, ignoring `skip_id`
+/// (a window that has just been created and is not placed yet).
+struct Columns<'a> {
+    master: &'a Node,
+    stack: Option<&'a Node>,
+    master_leaves: Vec<&'a Node>,
+    stack_leaves: Vec<&'a Node>,
+}
+
+fn columns(ws: &Node, skip_id: i64) -> Option<Columns<'_>> {
+    let mut cols = ws.nodes.iter().filter(|c| c.id != skip_id);
+    let master = cols.next()?;
+    let stack = cols.next();
+
+    let mut master_leaves = Vec::new();
+    collect_tiling_leaves(master, &mut master_leaves);
+    master_leaves.retain(|l| l.id != skip_id);
+
+    let mut stack_leaves = Vec::new();
+    if let Some(s) = stack {
+        collect_tiling_leaves(s, &mut stack_leaves);
+        stack_leaves.retain(|l| l.id != skip_id);
+    }
+
+    Some(Columns { master, stack, master_leaves, stack_leaves })
+}
+
+/// Move `id` right after `target` in target's parent. A bare window as the
+/// target is wrapped in a vertical split first so `id` stacks below it
+/// instead of becoming its horizontal sibling.
+fn move_below(id: i64, target: &Node, wrap: bool) -> String {
+    let split = if wrap {
+        format!("[con_id={}] split v; ", target.id)
+    } else {
+        String::new()
+    };
+    format!(
+        "{split}[con_id={t}] mark --add {MARK}; [con_id={id}] move container to mark {MARK}; unmark {MARK}",
+        t = target.id,
+    )
+}
+
+/// Make `id` the stack column: a top-level sibling right of `master`.
+fn open_stack(id: i64, master: &Node, fraction: f64) -> String {
+    format!(
+        "[con_id={m}] mark --add {MARK}; [con_id={id}] move container to mark {MARK}; unmark {MARK}; \
+         [con_id={id}] layout splith; [con_id={m}] resize set width {pct} ppt",
+        m = master.id,
+        pct = (fraction * 100.0).round() as i64,
+    )
+}
+
+/// Put newly created window `new_id` on workspace `ws` into the master area
+/// if it has room, otherwise at the bottom of the stack.
+pub fn place(cmd: &mut Connection, ws: &Node, new_id: i64, nmaster: usize, fraction: f64) -> Result<()> {
+    let mut all = Vec::new();
+    collect_tiling_leaves(ws, &mut all);
+    // Floating windows aren't in `nodes`; leave them alone.
+    if !all.iter().any(|l| l.id == new_id) {
+        return Ok(());
+    }
+    let cols = match columns(ws, new_id) {
+        Some(c) => c,
+        None => return Ok(()), // first window becomes master as-is
+    };
+
+    let c = match (cols.master_leaves.last(), cols.stack, cols.stack_leaves.last()) {
+        (Some(last), _, _) if cols.master_leaves.len() < nmaster.max(1) => {
+            move_below(new_id, last, cols.master.nodes.is_empty())
+        }
+        (_, Some(stack), Some(last)) => move_below(new_id, last, stack.nodes.is_empty()),
+        _ => open_stack(new_id, cols.master, fraction),
+    };
+    cmd.run_command(c)?;
+    Ok(())
+}
+
+/// The next move towards `nmaster` master windows; `None` once there.
+fn reflow_step(ws: &Node, nmaster: usize, fraction: f64) -> Option<String> {
+    let cols = columns(ws, -1)?;
+    let (m, s) = (cols.master_leaves.len(), cols.stack_leaves.len());
+    if m > nmaster {
+        let last = cols.master_leaves[m - 1];
+        Some(match (cols.stack, cols.stack_leaves.first()) {
+            // Below the first stack window, then up one: top of stack
+            (Some(stack), Some(first)) => format!(
+                "{}; [con_id={}] move up",
+                move_below(last.id, first, stack.nodes.is_empty()),
+                last.id
+            ),
+            _ => open_stack(last.id, cols.master, fraction),
+        })
+    } else if m < nmaster && s > 0 {
+        let (first, last) = (cols.stack_leaves.first()?, cols.master_leaves.last()?);
+        Some(move_below(first.id, last, cols.master.nodes.is_empty()))
+    } else {
+        None
+    }
+}
+
+/// Move windows between the columns until the master area holds exactly
+/// `nmaster` windows (or every window, if there are fewer).
+pub fn reflow(cmd: &mut Connection, ws_key: &str, nmaster: usize, fraction: f64) -> Result<()> {
+    let nmaster = nmaster.max(1);
+    for _ in 0..MAX_REFLOW_STEPS {
+        let tree = cmd.get_tree().context("get_tree failed")?;
+        let ws = match crate::find_workspace(&tree, ws_key) {
+            Some(ws) => ws,
+            None => return Ok(()),
+        };
+        match reflow_step(ws, nmaster, fraction) {
+            Some(c) => {
+                cmd.run_command(c)?;
+            }
+            None => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+/// The window `focused_id` swaps places with on `promote`.
+fn promote_target(ws: &Node, focused_id: i64) -> Result<i64> {
+    let cols = columns(ws, -1).context("workspace is empty")?;
+    let master = cols.master_leaves.first().context("no master window")?;
+    if master.id != focused_id {
+        return Ok(master.id);
+    }
+    Ok(cols.stack_leaves.first().context("no stack window to promote")?.id)
+}
+
+/// Swap the focused window with the first master window; if it already is
+/// the master, swap with the top of the stack (dwm's `zoom`).
+pub fn promote(cmd: &mut Connection, ws: &Node, focused_id: i64) -> Result<()> {
+    let other = promote_target(ws, focused_id)?;
+    cmd.run_command(format!("[con_id={focused_id}] swap container with con_id {other}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{leaf, split, workspace};
+
+    #[test]
+    fn reflow_moves_one_window_per_step() {
+        // master 1 | stack [2, 3]
+        let ws = workspace("splith", vec![leaf(1, 0, 0, 440, 600), split("splitv", vec![
+            leaf(2, 440, 0, 360, 300),
+            leaf(3, 440, 300, 360, 300),
+        ])]);
+        assert_eq!(reflow_step(&ws, 1, 0.55), None);
+        // Growing: the top of the stack goes below the (wrapped) master
+        assert_eq!(
+            reflow_step(&ws, 2, 0.55).as_deref(),
+            Some("[con_id=1] split v; [con_id=1] mark --add _i3h_master; \
+                  [con_id=2] move container to mark _i3h_master; unmark _i3h_master")
+        );
+
+        // master [1, 2] | stack 3: shrinking sends 2 to the top of the stack
+        let ws = workspace("splith", vec![
+            split("splitv", vec![leaf(1, 0, 0, 440, 300), leaf(2, 0, 300, 440, 300)]),
+            leaf(3, 440, 0, 360, 600),
+        ]);
+        let c = reflow_step(&ws, 1, 0.55).unwrap();
+        assert!(c.starts_with("[con_id=3] split v; [con_id=3] mark --add _i3h_master; [con_id=2] move"), "{c}");
+        assert!(c.ends_with("[con_id=2] move up"), "{c}");
+        assert_eq!(reflow_step(&ws, 2, 0.55), None);
+
+        // No stack yet: the extra window opens it
+        let ws = workspace("splith", vec![split("splitv", vec![
+            leaf(1, 0, 0, 800, 300),
+            leaf(2, 0, 300, 800, 300),
+        ])]);
+        let c = reflow_step(&ws, 1, 0.55).unwrap();
+        assert!(c.ends_with("[con_id=2] layout splith; [con_id=100] resize set width 55 ppt"), "{c}");
+    }
+
+    #[test]
+    fn promote_swaps_with_master_or_zooms_the_stack() {
+        let ws = workspace("splith", vec![leaf(1, 0, 0, 440, 600), split("splitv", vec![
+            leaf(2, 440, 0, 360, 300),
+            leaf(3, 440, 300, 360, 300),
+        ])]);
+        assert_eq!(promote_target(&ws, 3).unwrap(), 1);
+        assert_eq!(promote_target(&ws, 1).unwrap(), 2);
+        let alone = workspace("splith", vec![leaf(1, 0, 0, 800, 600)]);
+        assert!(promote_target(&alone, 1).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Focus history: most recently focused windows first.
+//!
+//! Fed from `focus`/`close` window events, so it spans workspaces and
+//! outputs; i3's own `focus` arrays are per container. Kept in the state
+//! file across daemon restarts (container ids are stable until i3 itself
+//! restarts; stale ids are pruned against the tree on reconnect), written
+//! a few seconds after it changes so a crash loses little.
+//!
+//!   i3-helper msg focus-last      # alt-tab: toggle with the previous window
+//!   i3-helper msg focus-prev 3    # three windows back
+//!   i3-helper msg list-mru        # history with class, title, workspace
+
+use crate::{find_focused, state, swallow, window_class};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use swayipc::{Connection, Node, NodeType};
+
+/// Windows remembered.
+const CAP: usize = 64;
+
+/// How long a change may sit in memory before the state file is written.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// Window ids, most recent first.
+#[derive(Debug, Default)]
+struct History(VecDeque<i64>);
+
+impl History {
+    fn focus(&mut self, id: i64) {
+        self.0.retain(|&x| x != id);
+        self.0.push_front(id);
+        self.0.truncate(CAP);
+    }
+
+    fn close(&mut self, id: i64) {
+        self.0.retain(|&x| x != id);
+    }
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History(VecDeque::new()));
+/// Set on every change; cleared by the writer thread.
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+#[inline]
+fn history() -> std::sync::MutexGuard<'static, History> {
+    HISTORY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn on_focus(id: i64) {
+    history().focus(id);
+    DIRTY.store(true, Ordering::Relaxed);
+}
+
+pub fn on_close(id: i64) {
+    history().close(id);
+    DIRTY.store(true, Ordering::Relaxed);
+}
+
+/// For the state file.
+pub fn snapshot() -> Vec<i64> {
+    history().0.iter().copied().collect()
+}
+
+/// From the state file.
+pub fn restore(ids: Vec<i64>) {
+    history().0 = ids.into_iter().take(CAP).collect();
+}
+
+/// Save the state file at most every `SAVE_DELAY` while focus moves.
+pub fn spawn_writer() {
+    thread::spawn(|| loop {
+        thread::sleep(SAVE_DELAY);
+        if DIRTY.swap(false, Ordering::Relaxed) {
+            state::save();
+        }
+    });
+}
+
+/// Every window in the tree (scratchpad included) with the name of its
+/// workspace.
+pub fn windows(tree: &Node) -> Vec<(&str, &Node)> {
+    fn walk<'a>(node: &'a Node, ws: &'a str, out: &mut Vec<(&'a str, &'a Node)>) {
+        let ws = match node.node_type {
+            NodeType::Workspace => node.name.as_deref().unwrap_or_default(),
+            _ => ws,
+        };
+        if node.nodes.is_empty() && node.floating_nodes.is_empty() {
+            if node.window.is_some() || node.app_id.is_some() {
+                out.push((ws, node));
+            }
+            return;
+        }
+        for c in node.nodes.iter().chain(node.floating_nodes.iter()) {
+            walk(c, ws, out);
+        }
+    }
+    let mut out = Vec::new();
+    walk(tree, "", &mut out);
+    out
+}
+
+/// Drop ids that are no longer windows (closed while the daemon was
+/// disconnected, or from before an i3 restart). Swallowed terminals stay in
+/// the history but are not offered until they come back.
+pub fn prune(tree: &Node) -> Vec<i64> {
+    let wins = windows(tree);
+    let mut h = history();
+    h.0.retain(|id| wins.iter().any(|(_, n)| n.id == *id));
+    h.0.iter()
+        .copied()
+        .filter(|id| wins.iter().any(|(_, n)| n.id == *id && !swallow::is_swallowed(n)))
+        .collect()
+}
+
+/// The window `n` steps back in `ids`. When focus sits on something that
+/// isn't the newest entry (an empty workspace), that entry is step one.
+fn pick(ids: &[i64], focused: Option<i64>, n: usize) -> Option<i64> {
+    let n = if ids.first().copied() == focused { n } else { n.saturating_sub(1) };
+    ids.get(n).copied()
+}
+
+/// Focus the window `n` steps back (1 = previous). Returns its id.
+pub fn focus_prev(cmd: &mut Connection, n: usize) -> Result<Option<i64>> {
+    let tree = cmd.get_tree().context("get_tree failed")?;
+    let ids = prune(&tree);
+    let id = match pick(&ids, find_focused(&tree).map(|f| f.id), n) {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    for outcome in cmd.run_command(format!("[con_id={id}] focus"))? {
+        outcome.context("focus failed")?;
+    }
+    Ok(Some(id))
+}
+
+/// History with enough to show the user.
+pub fn list(cmd: &mut Connection) -> Result<Vec<Value>> {
+    let tree = cmd.get_tree().context("get_tree failed")?;
+    let ids = prune(&tree);
+    let wins = windows(&tree);
+    Ok(ids
+        .iter()
+        .filter_map(|id| wins.iter().find(|(_, n)| n.id == *id))
+        .map(|(ws, n)| {
+            json!({
+                "id": n.id,
+                "class": window_class(n),
+                "title": n.name,
+                "workspace": ws,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn previous_window_is_one_step_back() {
+        let ids = [3, 2, 1];
+        assert_eq!(pick(&ids, Some(3), 1), Some(2));
+        assert_eq!(pick(&ids, Some(3), 2), Some(1));
+        assert_eq!(pick(&ids, Some(3), 3), None);
+        // On an empty workspace the last focused window is the previous one
+        assert_eq!(pick(&ids, None, 1), Some(3));
+    }
+
+    #[test]
+    fn focus_moves_to_front_and_close_forgets() {
+        let mut h = History::default();
+        for id in [1, 2, 3, 1] {
+            h.focus(id);
+        }
+        h.close(2);
+        assert_eq!(h.0, [1, 3]);
+    }
+}
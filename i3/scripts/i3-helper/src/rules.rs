@@ -0,0 +1,240 @@
+//! Window → icon rules.
+//!
+//! `window_class()` alone can't tell a kitty running nvim from one running
+//! btop. Rules match on any combination of window properties (regexes, i3
+//! criteria semantics: unanchored, case-sensitive unless `(?i)`), and name
+//! an entry of the icon map to use instead of the class:
+//!
+//!   [[rules]]
+//!   class = "^kitty$"
+//!   title = "n?vim"
+//!   icon = "nvim"
+//!   priority = 10      # optional: higher first, ties keep file order
+//!
+//! Under Sway, native Wayland windows have no X11 properties; match them
+//! with `app_id` instead of `class`/`instance`.
+//!
+//! All criteria of a rule must match. The first matching rule wins; windows
+//! no rule matches fall back to their class. The same `Matcher` finds the
+//! windows of auto marks (`marks`) and scratchpads (`scratch`).
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use swayipc::Node;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawRule {
+    class: Option<String>,
+    instance: Option<String>,
+    title: Option<String>,
+    window_role: Option<String>,
+    window_type: Option<String>,
+    app_id: Option<String>,
+    icon: String,
+    #[serde(default)]
+    priority: i32,
+}
+
+/// Window criteria; unset fields match anything.
+#[derive(Debug, Default)]
+pub struct Matcher {
+    pub class: Option<Regex>,
+    pub instance: Option<Regex>,
+    pub title: Option<Regex>,
+    pub window_role: Option<Regex>,
+    pub window_type: Option<Regex>,
+    /// Sway: Wayland app_id (X11 clients under Xwayland keep `class`).
+    pub app_id: Option<Regex>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    matcher: Matcher,
+    /// Key into the icon map; also the dedup key in "all" naming mode.
+    pub icon: String,
+    priority: i32,
+}
+
+/// Compile an optional criteria pattern named `field` in errors.
+pub fn regex(field: &str, pat: Option<&str>) -> Result<Option<Regex>> {
+    pat.map(|p| Regex::new(p).with_context(|| format!("{field}: invalid regex")))
+        .transpose()
+}
+
+/// Compile raw rules and sort them by priority (stable, so ties keep file order).
+pub fn compile_rules(raw: Vec<RawRule>) -> Result<Vec<Rule>> {
+    let mut rules = raw
+        .into_iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let compile = |field, pat: Option<String>| {
+                regex(&format!("rules[{i}].{field}"), pat.as_deref())
+            };
+            let matcher = Matcher {
+                class: compile("class", r.class)?,
+                instance: compile("instance", r.instance)?,
+                title: compile("title", r.title)?,
+                window_role: compile("window_role", r.window_role)?,
+                window_type: compile("window_type", r.window_type)?,
+                app_id: compile("app_id", r.app_id)?,
+            };
+            if matcher.is_empty() {
+                bail!("rules[{i}]: needs at least one of class, instance, title, window_role, window_type, app_id");
+            }
+            Ok(Rule { matcher, icon: r.icon.to_lowercase(), priority: r.priority })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    rules.sort_by_key(|r| std::cmp::Reverse(r.priority));
+    Ok(rules)
+}
+
+impl Matcher {
+    pub fn is_empty(&self) -> bool {
+        self.class.is_none()
+            && self.instance.is_none()
+            && self.title.is_none()
+            && self.window_role.is_none()
+            && self.window_type.is_none()
+            && self.app_id.is_none()
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        let wp = node.window_properties.as_ref();
+        // i3 puts the live title in `name`; window_properties.title can lag
+        let title = node.name.as_deref().or_else(|| wp.and_then(|p| p.title.as_deref()));
+
+        fn check(re: &Option<Regex>, value: Option<&str>) -> bool {
+            match re {
+                None => true,
+                Some(re) => value.is_some_and(|v| re.is_match(v)),
+            }
+        }
+
+        check(&self.class, wp.and_then(|p| p.class.as_deref()))
+            && check(&self.instance, wp.and_then(|p| p.instance.as_deref()))
+            && check(&self.title, title)
+            && check(&self.window_role, wp.and_then(|p| p.window_role.as_deref()))
+            && check(&self.window_type, wp.and_then(|p| p.window_type.as_deref()))
+            && check(&self.app_id, node.app_id.as_deref())
+    }
+}
+
+impl Rule {
+    #[inline]
+    pub fn matches(&self, node: &Node) -> bool {
+        self.matcher.matches(node)
+    }
+}
+
+/// Icon key of the first rule matching `node`.
+#[inline]
+pub fn match_icon<'a>(rules: &'a [Rule], node: &Node) -> Option<&'a str> {
+    rules.iter().find(|r| r.matches(node)).map(|r| r.icon.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{wayland_window, window};
+
+    fn rules(toml: &str) -> Vec<Rule> {
+        #[derive(Deserialize)]
+        struct Wrap {
+            rules: Vec<RawRule>,
+        }
+        let w: Wrap = toml::from_str(toml).expect("valid toml");
+        compile_rules(w.rules).expect("valid rules")
+    }
+
+    const TERMINALS: &str = r#"
+        [[rules]]
+        class = "^kitty$"
+        title = "n?vim"
+        icon = "nvim"
+
+        [[rules]]
+        class = "^kitty$"
+        title = "^btop"
+        icon = "btop"
+    "#;
+
+    #[test]
+    fn title_distinguishes_terminal_windows() {
+        let r = rules(TERMINALS);
+        let nvim = window("kitty", "kitty", "nvim ~/src/main.rs");
+        let btop = window("kitty", "kitty", "btop");
+        let shell = window("kitty", "kitty", "zsh");
+        assert_eq!(match_icon(&r, &nvim), Some("nvim"));
+        assert_eq!(match_icon(&r, &btop), Some("btop"));
+        assert_eq!(match_icon(&r, &shell), None);
+    }
+
+    #[test]
+    fn all_criteria_must_match() {
+        let r = rules(TERMINALS);
+        let alacritty = window("Alacritty", "alacritty", "nvim");
+        assert_eq!(match_icon(&r, &alacritty), None);
+    }
+
+    #[test]
+    fn priority_beats_file_order() {
+        let r = rules(
+            r#"
+            [[rules]]
+            class = "kitty"
+            icon = "kitty"
+
+            [[rules]]
+            title = "htop"
+            icon = "htop"
+            priority = 5
+            "#,
+        );
+        assert_eq!(match_icon(&r, &window("kitty", "kitty", "htop")), Some("htop"));
+        assert_eq!(match_icon(&r, &window("kitty", "kitty", "zsh")), Some("kitty"));
+    }
+
+    #[test]
+    fn role_and_instance() {
+        let r = rules(
+            r#"
+            [[rules]]
+            instance = "^Navigator$"
+            window_role = "^browser$"
+            icon = "firefox"
+            "#,
+        );
+        let mut w = window("firefox", "Navigator", "Mozilla Firefox");
+        assert_eq!(match_icon(&r, &w), None, "no role set");
+        if let Some(p) = w.window_properties.as_mut() {
+            p.window_role = Some("browser".into());
+        }
+        assert_eq!(match_icon(&r, &w), Some("firefox"));
+    }
+
+    #[test]
+    fn app_id_matches_wayland_windows_only() {
+        let r = rules(
+            r#"
+            [[rules]]
+            app_id = "^foot$"
+            title = "n?vim"
+            icon = "nvim"
+            "#,
+        );
+        assert_eq!(match_icon(&r, &wayland_window("foot", "nvim")), Some("nvim"));
+        assert_eq!(match_icon(&r, &wayland_window("foot", "zsh")), None);
+        // X11 windows have no app_id
+        assert_eq!(match_icon(&r, &window("foot", "foot", "nvim")), None);
+    }
+
+    #[test]
+    fn rejects_empty_and_bad_rules() {
+        let empty: Vec<RawRule> = vec![toml::from_str(r#"icon = "x""#).unwrap()];
+        assert!(compile_rules(empty).is_err());
+        let bad: Vec<RawRule> = vec![toml::from_str("title = '('\nicon = 'x'").unwrap()];
+        assert!(compile_rules(bad).is_err());
+    }
+}
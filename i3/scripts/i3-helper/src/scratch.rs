@@ -0,0 +1,638 @@
+//! Named scratchpads: `i3-helper scratch <name>`.
+//!
+//! Each `[scratchpads.<name>]` entry is an app that lives in the
+//! scratchpad: how to start it, criteria for its window, and where it
+//! shows up, sized in percent of the focused output:
+//!
+//!   [scratchpads.notes]
+//!   command = "obsidian"        # run by `sh -c`
+//!   class = "^obsidian$"        # also instance, title, app_id (regexes)
+//!   width = 70
+//!   height = 80
+//!   position = "right"          # center | top | bottom | left | right
+//!
+//! `scratch <name>` starts the app when no window matches (and moves its
+//! window into the scratchpad once it appears), shows it when hidden and
+//! hides it when shown and focused. Showing one hides every other named
+//! scratchpad on screen. A matching window that isn't floating yet (started
+//! by hand) is taken over.
+//!
+//! With `dismiss_on_blur = true` the daemon sends a shown scratchpad back
+//! once focus moves to another window or workspace and stays there for
+//! `grace` milliseconds (default 300). Windows that open while it has focus
+//! count as its dialogs: focusing them doesn't dismiss it.
+//!
+//! Built in, each in its own kitty: `term` (tmux, from `scratchpad.conf`),
+//! `music` (rmpc), `gpu` (nvtop), `top` (btop), `docker` (lazydocker),
+//! `mixer` (pulsemixer) and `news` (newsboat), bound in `apps.conf`. An
+//! entry of the same name replaces one.
+
+use crate::config;
+use crate::marks::Criteria;
+use crate::rules::Matcher;
+use crate::{focused_workspace, is_scratchpad_workspace, wm};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
+use std::process::{Command as Cmd, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use swayipc::{Connection, Event, EventType, Node, NodeType, Rect, WindowChange};
+
+/// How long a started app gets to map its window.
+const SPAWN_WAIT: Duration = Duration::from_secs(10);
+
+const KITTY: &str = "kitty --config ~/.config/kitty/kittys.conf";
+
+// ── Config ────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+fn default_size() -> u32 {
+    60
+}
+
+fn default_grace() -> u64 {
+    300
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawScratchpad {
+    command: String,
+    class: Option<String>,
+    instance: Option<String>,
+    title: Option<String>,
+    app_id: Option<String>,
+    /// Percent of the output width.
+    #[serde(default = "default_size")]
+    width: u32,
+    /// Percent of the output height.
+    #[serde(default = "default_size")]
+    height: u32,
+    #[serde(default)]
+    position: Position,
+    /// Hide again when focus moves elsewhere.
+    #[serde(default)]
+    dismiss_on_blur: bool,
+    /// Milliseconds focus must stay away before it is hidden.
+    #[serde(default = "default_grace")]
+    grace: u64,
+}
+
+#[derive(Debug)]
+pub struct Scratchpad {
+    pub command: String,
+    matcher: Matcher,
+    width: u32,
+    height: u32,
+    position: Position,
+    pub dismiss_on_blur: bool,
+    pub grace: Duration,
+}
+
+impl Scratchpad {
+    #[inline]
+    pub fn matches(&self, node: &Node) -> bool {
+        self.matcher.matches(node)
+    }
+}
+
+fn compile_one(name: &str, r: RawScratchpad) -> Result<Scratchpad> {
+    for (field, pct) in [("width", r.width), ("height", r.height)] {
+        if !(10..=100).contains(&pct) {
+            bail!("scratchpads.{name}.{field} must be between 10 and 100 (percent)");
+        }
+    }
+    let c = Criteria { class: r.class, instance: r.instance, title: r.title, app_id: r.app_id };
+    let matcher = c.compile().with_context(|| format!("scratchpads.{name}"))?;
+    Ok(Scratchpad {
+        command: r.command,
+        matcher,
+        width: r.width,
+        height: r.height,
+        position: r.position,
+        dismiss_on_blur: r.dismiss_on_blur,
+        grace: Duration::from_millis(r.grace),
+    })
+}
+
+/// Built-in scratchpads: (name, kitty class, program, width, height,
+/// position, dismiss_on_blur).
+const BUILTIN: &[(&str, &str, &str, u32, u32, Position, bool)] = &[
+    ("term", "scratchpad", "-T scratchpad tmux new-session -As scratchpad", 50, 75, Position::Center, true),
+    ("music", "scratch-music", "rmpc", 60, 50, Position::Top, true),
+    ("gpu", "scratch-gpu", "nvtop", 70, 70, Position::Center, false),
+    ("top", "scratch-top", "btop", 70, 70, Position::Center, false),
+    ("docker", "scratch-docker", "lazydocker", 70, 70, Position::Center, false),
+    ("mixer", "scratch-mixer", "pulsemixer", 35, 50, Position::Right, true),
+    ("news", "scratch-news", "newsboat", 60, 70, Position::Center, false),
+];
+
+/// The built-in scratchpads.
+pub fn builtin() -> Result<HashMap<String, Scratchpad>> {
+    BUILTIN
+        .iter()
+        .map(|&(name, class, program, width, height, position, dismiss_on_blur)| {
+            let raw = RawScratchpad {
+                command: format!("{KITTY} --class {class} {program}"),
+                class: Some(format!("^{class}$")),
+                instance: None,
+                title: None,
+                app_id: None,
+                width,
+                height,
+                position,
+                dismiss_on_blur,
+                grace: default_grace(),
+            };
+            Ok((name.to_string(), compile_one(name, raw)?))
+        })
+        .collect()
+}
+
+/// Built-ins plus `[scratchpads.*]`, entries of the same name replacing
+/// built-ins.
+pub fn compile(raw: HashMap<String, RawScratchpad>) -> Result<HashMap<String, Scratchpad>> {
+    let mut pads = builtin()?;
+    for (name, r) in raw {
+        let pad = compile_one(&name, r)?;
+        pads.insert(name, pad);
+    }
+    Ok(pads)
+}
+
+// ── Toggling ──────────────────────────────────────────────────
+
+/// A window with where it is.
+struct Win<'a> {
+    node: &'a Node,
+    /// Workspace id.
+    ws: i64,
+    /// In i3's scratchpad (hidden).
+    hidden: bool,
+    floating: bool,
+}
+
+fn windows(tree: &Node) -> Vec<Win<'_>> {
+    fn walk<'a>(node: &'a Node, ws: Option<&'a Node>, floating: bool, out: &mut Vec<Win<'a>>) {
+        let ws = if node.node_type == NodeType::Workspace { Some(node) } else { ws };
+        if node.nodes.is_empty() && node.floating_nodes.is_empty() {
+            if let (Some(ws), true) = (ws, node.window.is_some() || node.app_id.is_some()) {
+                out.push(Win { node, ws: ws.id, hidden: is_scratchpad_workspace(ws), floating });
+            }
+            return;
+        }
+        for c in &node.nodes {
+            walk(c, ws, floating, out);
+        }
+        for c in &node.floating_nodes {
+            walk(c, ws, true, out);
+        }
+    }
+    let mut out = Vec::new();
+    walk(tree, None, false, &mut out);
+    out
+}
+
+/// Area of the output workspace `ws` is on; the workspace's own rect if
+/// it can't be found.
+fn output_area(tree: &Node, ws: &Node) -> Rect {
+    fn contains(node: &Node, id: i64) -> bool {
+        node.id == id || node.nodes.iter().any(|c| contains(c, id))
+    }
+    tree.nodes.iter().find(|o| contains(o, ws.id)).map_or(ws.rect, |o| o.rect)
+}
+
+/// Resize and place a floating window inside `area`.
+fn geometry(area: &Rect, pad: &Scratchpad) -> String {
+    let w = area.width * pad.width as i32 / 100;
+    let h = area.height * pad.height as i32 / 100;
+    let cx = area.x + (area.width - w) / 2;
+    let cy = area.y + (area.height - h) / 2;
+    let (x, y) = match pad.position {
+        Position::Center => (cx, cy),
+        Position::Top => (cx, area.y),
+        Position::Bottom => (cx, area.y + area.height - h),
+        Position::Left => (area.x, cy),
+        Position::Right => (area.x + area.width - w, cy),
+    };
+    format!("resize set {w} px {h} px, move absolute position {x} px {y} px")
+}
+
+/// Commands that show window `id` on the focused workspace. A window on
+/// screen goes through the scratchpad first, which also makes it floating.
+fn show(id: i64, on_screen: bool, area: &Rect, pad: &Scratchpad) -> String {
+    let park = if on_screen { format!("[con_id={id}] move scratchpad; ") } else { String::new() };
+    format!("{park}[con_id={id}] scratchpad show; [con_id={id}] {}", geometry(area, pad))
+}
+
+#[derive(Debug, PartialEq)]
+enum Toggle {
+    /// Run these commands.
+    Run(Vec<String>),
+    /// Hide these, then start the app.
+    Spawn(Vec<String>),
+}
+
+fn toggle(
+    wins: &[Win],
+    ws: &Node,
+    area: &Rect,
+    name: &str,
+    pads: &HashMap<String, Scratchpad>,
+) -> Option<Toggle> {
+    let pad = pads.get(name)?;
+    let hide_others: Vec<String> = wins
+        .iter()
+        .filter(|w| !w.hidden && w.floating)
+        .filter(|w| pads.iter().any(|(n, p)| n != name && p.matches(w.node)))
+        .map(|w| format!("[con_id={}] move scratchpad", w.node.id))
+        .collect();
+
+    // One on screen first, so a second press hides what the first showed
+    let mut mine: Vec<&Win> = wins.iter().filter(|w| pad.matches(w.node)).collect();
+    mine.sort_by_key(|w| w.hidden);
+    let w = match mine.first() {
+        Some(w) => w,
+        None => return Some(Toggle::Spawn(hide_others)),
+    };
+    let id = w.node.id;
+    let mut cmds = hide_others;
+    match (w.hidden, w.floating) {
+        (false, true) if w.node.focused => return Some(Toggle::Run(vec![format!("[con_id={id}] move scratchpad")])),
+        (false, true) if w.ws == ws.id => cmds.push(format!("[con_id={id}] focus")),
+        (hidden, _) => cmds.push(show(id, !hidden, area, pad)),
+    }
+    Some(Toggle::Run(cmds))
+}
+
+fn run(conn: &mut Connection, cmds: &[String]) -> Result<()> {
+    if cmds.is_empty() {
+        return Ok(());
+    }
+    for outcome in conn.run_command(cmds.join("; "))? {
+        outcome.context("scratchpad command failed")?;
+    }
+    Ok(())
+}
+
+/// Start `pad` and wait for its window. Returns the container id.
+fn spawn(name: &str, pad: &Scratchpad) -> Result<i64> {
+    // Subscribe before starting so the window can't slip by; the title
+    // may only match after the first retitle
+    let events = wm::connect()?
+        .subscribe([EventType::Window])
+        .context("Failed to subscribe to window events")?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events {
+            match event {
+                Ok(Event::Window(w)) if matches!(w.change, WindowChange::New | WindowChange::Title) => {
+                    if tx.send(w.container).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    });
+
+    Cmd::new("sh")
+        .args(["-c", &pad.command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("scratchpad '{name}': failed to start"))?;
+
+    let deadline = Instant::now() + SPAWN_WAIT;
+    while let Ok(con) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if pad.matches(&con) {
+            return Ok(con.id);
+        }
+    }
+    bail!("scratchpad '{name}': no matching window within {}s", SPAWN_WAIT.as_secs())
+}
+
+// ── Dismiss on Blur ───────────────────────────────────────────
+// Fed from the daemon's focus events. A blur arms a timer thread; any
+// later focus change bumps `gen`, which disarms it.
+
+struct Blur {
+    /// Focused dismissable scratchpad window and its grace period.
+    shown: Option<(i64, Duration)>,
+    /// Windows opened while it had focus.
+    dialogs: Vec<i64>,
+    /// Focus is on the shown window or one of its dialogs — not in the
+    /// grace period after a blur, when new windows belong to whatever
+    /// took focus.
+    focused: bool,
+    gen: u64,
+}
+
+static BLUR: Mutex<Blur> = Mutex::new(Blur { shown: None, dialogs: Vec::new(), focused: false, gen: 0 });
+
+impl Blur {
+    /// Focus moved to `id`; `pad` is its grace period if it is a
+    /// dismissable scratchpad. Returns a hide to schedule: (window, delay,
+    /// generation).
+    fn focus(&mut self, id: i64, pad: Option<Duration>) -> Option<(i64, Duration, u64)> {
+        self.gen += 1;
+        let prev = match self.shown {
+            Some((prev, _)) if prev == id || self.dialogs.contains(&id) => {
+                self.focused = true;
+                return None;
+            }
+            prev => prev,
+        };
+        self.focused = pad.is_some();
+        if let Some(grace) = pad {
+            self.shown = Some((id, grace));
+            self.dialogs.clear();
+        }
+        // Another scratchpad taking focus dismisses the first one too
+        prev.map(|(prev, grace)| (prev, grace, self.gen))
+    }
+
+    /// Window `id` opened: a dialog of the shown window if that has focus.
+    /// Its focus event follows, and must not count as a blur.
+    fn open(&mut self, id: i64) {
+        if self.shown.is_some() && self.focused {
+            self.dialogs.push(id);
+            self.gen += 1;
+        }
+    }
+
+    /// Whether the hide scheduled as `gen` still stands; it clears the
+    /// shown window if so.
+    fn fire(&mut self, id: i64, gen: u64) -> bool {
+        if self.gen != gen {
+            return false;
+        }
+        if self.shown.is_some_and(|(s, _)| s == id) {
+            self.shown = None;
+            self.dialogs.clear();
+        }
+        true
+    }
+}
+
+#[inline]
+fn blur() -> std::sync::MutexGuard<'static, Blur> {
+    BLUR.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hide `id` unless it is hidden, focused or no longer floating by now.
+fn dismiss(id: i64) -> Result<()> {
+    let mut conn = wm::connect()?;
+    let tree = conn.get_tree().context("get_tree failed")?;
+    if windows(&tree).iter().any(|w| w.node.id == id && !w.hidden && w.floating && !w.node.focused) {
+        run(&mut conn, &[format!("[con_id={id}] move scratchpad")])?;
+    }
+    Ok(())
+}
+
+fn schedule(hide: Option<(i64, Duration, u64)>) {
+    let Some((id, delay, gen)) = hide else {
+        return;
+    };
+    thread::spawn(move || {
+        thread::sleep(delay);
+        if !blur().fire(id, gen) {
+            return;
+        }
+        if let Err(e) = dismiss(id) {
+            eprintln!("i3-helper: scratchpad: {e:#}");
+        }
+    });
+}
+
+/// A window took focus.
+pub fn on_focus(node: &Node) {
+    let grace = config::get()
+        .scratchpads
+        .values()
+        .find(|p| p.dismiss_on_blur && p.matches(node))
+        .map(|p| p.grace);
+    let hide = blur().focus(node.id, grace);
+    schedule(hide);
+}
+
+/// A workspace took focus (possibly an empty one: no window focus follows).
+pub fn on_workspace_focus(ws_id: i64) {
+    let hide = blur().focus(ws_id, None);
+    schedule(hide);
+}
+
+/// A window opened: a dialog of the shown scratchpad while it has focus.
+pub fn on_new(id: i64) {
+    blur().open(id);
+}
+
+pub fn on_close(id: i64) {
+    let mut b = blur();
+    if b.shown.is_some_and(|(s, _)| s == id) {
+        b.shown = None;
+        b.dialogs.clear();
+    }
+    b.dialogs.retain(|&d| d != id);
+}
+
+/// `i3-helper scratch <NAME>`
+pub fn run_client(args: &[String]) -> Result<()> {
+    let name = match args {
+        [name] => name.as_str(),
+        _ => {
+            eprintln!("Usage: i3-helper scratch <NAME>");
+            std::process::exit(1);
+        }
+    };
+    let cfg = config::get();
+    let pad = match cfg.scratchpads.get(name) {
+        Some(p) => p,
+        None => {
+            let msg = format!("unknown scratchpad '{name}' (no [scratchpads.{name}] in the config)");
+            let _ = Cmd::new("notify-send").args(["-t", "2000", "i3-helper", &msg]).status();
+            bail!(msg);
+        }
+    };
+
+    let mut conn = wm::connect()?;
+    let tree = conn.get_tree().context("get_tree failed")?;
+    let ws = focused_workspace(&tree).context("no focused workspace")?;
+    let area = output_area(&tree, ws);
+    match toggle(&windows(&tree), ws, &area, name, &cfg.scratchpads) {
+        Some(Toggle::Run(cmds)) => run(&mut conn, &cmds),
+        Some(Toggle::Spawn(hide)) => {
+            run(&mut conn, &hide)?;
+            let id = spawn(name, pad)?;
+            // Re-read: the window may have opened tiled or been moved by a
+            // for_window rule
+            let tree = conn.get_tree().context("get_tree failed")?;
+            let on_screen = windows(&tree).iter().any(|w| w.node.id == id && !w.hidden);
+            run(&mut conn, &[show(id, on_screen, &area, pad)])
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{node, raw};
+    use serde_json::{json, Value};
+
+    fn pad(class: &str, position: Position) -> Scratchpad {
+        let raw = RawScratchpad {
+            command: "true".into(),
+            class: Some(class.into()),
+            instance: None,
+            title: None,
+            app_id: None,
+            width: 50,
+            height: 40,
+            position,
+            dismiss_on_blur: false,
+            grace: 0,
+        };
+        compile_one("t", raw).unwrap()
+    }
+
+    fn win(id: i64, class: &str) -> Value {
+        let mut v = raw(id, "con");
+        v["window"] = (0x1200000 + id).into();
+        v["window_properties"] = json!({ "class": class, "instance": class });
+        v
+    }
+
+    fn floating(w: Value) -> Value {
+        let mut fc = raw(w["id"].as_i64().unwrap() + 100, "floating_con");
+        fc["nodes"] = json!([w]);
+        fc
+    }
+
+    #[test]
+    fn geometry_is_a_share_of_the_output() {
+        let area: Rect = serde_json::from_value(json!({ "x": 1920, "y": 30, "width": 2000, "height": 1000 })).unwrap();
+        let g = geometry(&area, &pad("x", Position::Center));
+        assert_eq!(g, "resize set 1000 px 400 px, move absolute position 2420 px 330 px");
+        let g = geometry(&area, &pad("x", Position::Top));
+        assert!(g.ends_with("position 2420 px 30 px"), "{g}");
+    }
+
+    #[test]
+    fn show_toggles_and_hides_the_others() {
+        let pads = HashMap::from([
+            ("music".to_string(), pad("^music$", Position::Center)),
+            ("term".to_string(), pad("^scratchpad$", Position::Center)),
+        ]);
+        let mut ws = raw(10, "workspace");
+        ws["num"] = 1.into();
+        ws["nodes"] = json!([win(1, "firefox")]);
+        ws["floating_nodes"] = json!([floating(win(2, "scratchpad"))]);
+        let mut scratch = raw(20, "workspace");
+        scratch["name"] = "__i3_scratch".into();
+        scratch["num"] = (-1).into();
+        scratch["floating_nodes"] = json!([floating(win(3, "music"))]);
+        let mut root = raw(0, "root");
+        root["nodes"] = json!([ws, scratch]);
+        let tree = node(root);
+        let ws = &tree.nodes[0];
+        let wins = windows(&tree);
+        let area = ws.rect;
+
+        // Hidden music: the terminal goes away, music comes back
+        let Some(Toggle::Run(c)) = toggle(&wins, ws, &area, "music", &pads) else { panic!() };
+        assert_eq!(c[0], "[con_id=2] move scratchpad");
+        assert!(c[1].starts_with("[con_id=3] scratchpad show; [con_id=3] resize set"), "{}", c[1]);
+
+        // Visible but unfocused terminal: just focus it
+        assert_eq!(toggle(&wins, ws, &area, "term", &pads), Some(Toggle::Run(vec!["[con_id=2] focus".into()])));
+
+        let pads = HashMap::from([("notes".to_string(), pad("^obsidian$", Position::Center))]);
+        assert_eq!(toggle(&wins, ws, &area, "notes", &pads), Some(Toggle::Spawn(vec![])));
+    }
+
+    #[test]
+    fn blur_waits_out_dialogs_and_returns() {
+        let grace = Duration::from_millis(300);
+        let mut b = Blur { shown: None, dialogs: Vec::new(), focused: false, gen: 0 };
+        assert_eq!(b.focus(1, None), None);
+        assert_eq!(b.focus(7, Some(grace)), None);
+
+        // A dialog opens and takes focus: no blur
+        b.open(8);
+        assert_eq!(b.focus(8, None), None);
+
+        // Focus leaves, then comes back within the grace period
+        let (id, _, gen) = b.focus(1, None).unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(b.focus(7, Some(grace)), None);
+        assert!(!b.fire(id, gen));
+
+        // Leaves for good
+        let (id, delay, gen) = b.focus(2, None).unwrap();
+        assert_eq!(delay, grace);
+        assert!(b.fire(id, gen));
+        assert_eq!(b.shown, None);
+    }
+
+    #[test]
+    fn windows_opened_after_blur_are_not_dialogs() {
+        let grace = Duration::from_millis(300);
+        let mut b = Blur { shown: None, dialogs: Vec::new(), focused: false, gen: 0 };
+        assert_eq!(b.focus(7, Some(grace)), None);
+
+        // $mod+2, then a terminal opens there within the grace period
+        assert!(b.focus(20, None).is_some());
+        b.open(9);
+        assert!(b.dialogs.is_empty());
+        // Its focus is one more blur, not a return to the scratchpad
+        let (id, _, gen) = b.focus(9, None).unwrap();
+        assert_eq!(id, 7);
+        assert!(b.fire(id, gen));
+    }
+
+    #[test]
+    fn sized_against_the_output_not_the_workspace() {
+        // A bar takes 30 px off the workspace
+        let mut ws = raw(10, "workspace");
+        ws["rect"] = json!({ "x": 1920, "y": 30, "width": 1920, "height": 1050 });
+        let mut content = raw(3, "con");
+        content["nodes"] = json!([ws]);
+        let mut output = raw(2, "output");
+        output["rect"] = json!({ "x": 1920, "y": 0, "width": 1920, "height": 1080 });
+        output["nodes"] = json!([content]);
+        let mut root = raw(1, "root");
+        root["nodes"] = json!([raw(4, "output"), output]);
+        let tree = node(root);
+        let ws = &tree.nodes[1].nodes[0].nodes[0];
+        assert_eq!((output_area(&tree, ws).y, output_area(&tree, ws).height), (0, 1080));
+    }
+
+    #[test]
+    fn launcher_scratchpads_are_built_in() {
+        // Every built-in compiles
+        let pads = builtin().unwrap();
+        for name in ["term", "music", "gpu", "top", "docker", "mixer", "news"] {
+            assert!(pads.contains_key(name), "{name}");
+        }
+        assert!(pads["mixer"].dismiss_on_blur);
+        assert!(pads["music"].command.ends_with("--class scratch-music rmpc"));
+        assert!(pads["term"].matches(&crate::testutil::window("scratchpad", "scratchpad", "tmux")));
+    }
+}
@@ -0,0 +1,505 @@
+//! Session snapshots and crash recovery: `i3-helper session save|restore`.
+//!
+//! The daemon records every window — app (class/instance or `app_id`),
+//! workspace, output, floating state and the command line of its process
+//! (the tree's pid on Sway, `_NET_WM_PID` on i3 → `/proc/<pid>/cmdline`)
+//! — and writes it to `$XDG_STATE_HOME/i3-helper/session.json` at most
+//! every `session.interval` seconds.
+//!
+//! Each snapshot carries the IPC socket of the WM session that wrote it
+//! (i3 and Sway put their pid in the path). The first write in a new WM
+//! session moves the old file to `session.prev.json`, so apps autostarted
+//! after login can't clobber the session that is about to be restored;
+//! `restore` uses the newest snapshot from another session.
+//!
+//! `restore` is idempotent: running windows are matched to snapshot entries
+//! first and only moved, the rest are launched (once per recorded process)
+//! and moved to their workspace as their windows appear.
+
+use crate::{
+    collect_leaves, collect_tiling_leaves, collect_workspaces, config, proc_cmdline, state, window_pid, wm,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command as Cmd, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
+use swayipc::{Connection, Event, EventType, Node, WindowChange};
+
+/// How long `restore` waits for launched apps to map their windows.
+const WAIT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Window {
+    class: Option<String>,
+    instance: Option<String>,
+    app_id: Option<String>,
+    title: String,
+    /// Workspace number (i3 reports -1 for named workspaces) and name.
+    num: Option<i32>,
+    workspace: String,
+    output: String,
+    floating: bool,
+    pid: Option<i32>,
+    /// Filled in when written, not on every tree update.
+    command: Vec<String>,
+}
+
+/// (class, instance, app_id): what identifies an app across sessions.
+type App<'a> = (Option<&'a str>, Option<&'a str>, Option<&'a str>);
+
+impl Window {
+    fn app(&self) -> App<'_> {
+        (self.class.as_deref(), self.instance.as_deref(), self.app_id.as_deref())
+    }
+}
+
+fn node_app(n: &Node) -> App<'_> {
+    let wp = n.window_properties.as_ref();
+    (
+        wp.and_then(|p| p.class.as_deref()),
+        wp.and_then(|p| p.instance.as_deref()),
+        n.app_id.as_deref(),
+    )
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Snapshot {
+    /// IPC socket of the WM session that took it.
+    socket: Option<String>,
+    windows: Vec<Window>,
+}
+
+/// Latest snapshot from the event loop; `DIRTY` until it is on disk.
+static LATEST: Mutex<Option<Snapshot>> = Mutex::new(None);
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+fn path() -> PathBuf {
+    state::path().with_file_name("session.json")
+}
+
+fn prev_path() -> PathBuf {
+    state::path().with_file_name("session.prev.json")
+}
+
+/// Every window on a regular workspace, with its container id. The
+/// scratchpad and empty placeholders (`append_layout`) are left out.
+fn capture(tree: &Node) -> Vec<(i64, Window)> {
+    let mut out = Vec::new();
+    for output in tree.nodes.iter().filter(|o| o.name.as_deref() != Some("__i3")) {
+        let mut ws_buf = Vec::new();
+        collect_workspaces(output, &mut ws_buf);
+        for ws in ws_buf {
+            let mut tiling = Vec::new();
+            collect_tiling_leaves(ws, &mut tiling);
+            let mut floating = Vec::new();
+            for fc in &ws.floating_nodes {
+                collect_leaves(fc, &mut floating);
+            }
+            let leaves = tiling.iter().map(|n| (n, false)).chain(floating.iter().map(|n| (n, true)));
+            for (n, floating) in leaves {
+                if n.window.is_none() && n.app_id.is_none() {
+                    continue;
+                }
+                let (class, instance, app_id) = node_app(n);
+                out.push((
+                    n.id,
+                    Window {
+                        class: class.map(str::to_string),
+                        instance: instance.map(str::to_string),
+                        app_id: app_id.map(str::to_string),
+                        title: n.name.clone().unwrap_or_default(),
+                        num: ws.num,
+                        workspace: ws.name.clone().unwrap_or_default(),
+                        output: output.name.clone().unwrap_or_default(),
+                        floating,
+                        pid: window_pid(n),
+                        command: Vec::new(),
+                    },
+                ));
+            }
+        }
+    }
+    out
+}
+
+// ── Daemon side ───────────────────────────────────────────────
+
+/// Record the windows in a fresh tree. Cheap: no I/O, the writer thread
+/// picks it up.
+pub fn update(tree: &Node) {
+    if config::get().session.interval == 0 {
+        return;
+    }
+    let snap = Snapshot {
+        socket: wm::socket(),
+        windows: capture(tree).into_iter().map(|(_, w)| w).collect(),
+    };
+    let mut latest = LATEST.lock().unwrap_or_else(|e| e.into_inner());
+    if latest.as_ref() != Some(&snap) {
+        *latest = Some(snap);
+        DIRTY.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Write the latest snapshot every `session.interval` seconds if it changed.
+///
+/// Deliberately not flushed on shutdown: a logout closes the windows one by
+/// one before the daemon is stopped, and that teardown is not the session
+/// worth restoring.
+pub fn spawn_writer() {
+    thread::spawn(|| loop {
+        let secs = config::get().session.interval;
+        thread::sleep(Duration::from_secs(if secs == 0 { 5 } else { secs }));
+        if !DIRTY.swap(false, Ordering::Relaxed) {
+            continue;
+        }
+        let snap = LATEST.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(mut snap) = snap {
+            if let Err(e) = write(&mut snap) {
+                eprintln!("i3-helper: session not saved: {e:#}");
+            }
+        }
+    });
+}
+
+fn load(path: &Path) -> Result<Option<Snapshot>> {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let snap = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(snap))
+}
+
+fn write(snap: &mut Snapshot) -> Result<()> {
+    let mut commands: HashMap<i32, Vec<String>> = HashMap::new();
+    for w in &mut snap.windows {
+        if let Some(pid) = w.pid {
+            w.command = commands.entry(pid).or_insert_with(|| proc_cmdline(Some(pid))).clone();
+        }
+    }
+
+    let path = path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // First write in a new WM session: keep the previous one for `restore`
+    if let Ok(Some(old)) = load(&path) {
+        if old.socket != snap.socket {
+            let _ = fs::rename(&path, prev_path());
+        }
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(snap)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+// ── Restore ───────────────────────────────────────────────────
+
+/// Newest snapshot taken in a different WM session than `current`.
+fn pick(current: Option<&str>) -> Result<Snapshot> {
+    for p in [path(), prev_path()] {
+        match load(&p)? {
+            Some(snap) if current.is_none() || snap.socket.as_deref() != current => return Ok(snap),
+            _ => {}
+        }
+    }
+    bail!("no snapshot from a previous session ({})", path().display())
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `workspace …` argument: by number where there is one, since the daemon
+/// relabels numbered workspaces with icons.
+fn ws_target(w: &Window) -> String {
+    match w.num {
+        Some(n) if n >= 0 => format!("number {n}"),
+        _ => quote(&w.workspace),
+    }
+}
+
+fn on_workspace(w: &Window, num: i32, name: &str) -> bool {
+    match w.num {
+        Some(n) if n >= 0 => num == n,
+        _ => name == w.workspace,
+    }
+}
+
+/// Commands that put container `id` where `want` was. `now` is where it is,
+/// if known; nothing is sent for what already matches.
+fn place(id: i64, want: &Window, now: Option<&Window>) -> Option<String> {
+    let mut c = Vec::new();
+    if !now.is_some_and(|n| on_workspace(want, n.num.unwrap_or(-1), &n.workspace)) {
+        c.push(format!("[con_id={id}] move container to workspace {}", ws_target(want)));
+    }
+    if now.map(|n| n.floating) != Some(want.floating) {
+        let state = if want.floating { "enable" } else { "disable" };
+        c.push(format!("[con_id={id}] floating {state}"));
+    }
+    (!c.is_empty()).then(|| c.join("; "))
+}
+
+#[derive(Debug, Default)]
+struct Plan {
+    /// Moves for windows that are already running.
+    cmds: Vec<String>,
+    /// Indices of snapshot windows with no running counterpart.
+    missing: Vec<usize>,
+}
+
+/// Match saved windows to running ones (same title first, then same app)
+/// so each running window stands in for at most one saved window.
+fn plan(saved: &[Window], running: &[(i64, Window)]) -> Plan {
+    let mut matched: Vec<Option<usize>> = vec![None; saved.len()];
+    let mut used = HashSet::new();
+    for same_title in [true, false] {
+        for (i, s) in saved.iter().enumerate() {
+            if matched[i].is_some() {
+                continue;
+            }
+            let found = running.iter().position(|(id, r)| {
+                !used.contains(id) && r.app() == s.app() && (!same_title || r.title == s.title)
+            });
+            if let Some(j) = found {
+                used.insert(running[j].0);
+                matched[i] = Some(j);
+            }
+        }
+    }
+
+    let mut plan = Plan::default();
+    for (i, m) in matched.into_iter().enumerate() {
+        match m {
+            Some(j) => plan.cmds.extend(place(running[j].0, &saved[i], Some(&running[j].1))),
+            None => plan.missing.push(i),
+        }
+    }
+    plan
+}
+
+/// Move workspaces back to their recorded outputs (where those are
+/// connected), keeping focus where it was.
+fn fix_outputs(conn: &mut Connection, snap: &Snapshot) -> Result<()> {
+    let outputs: HashSet<String> = conn.get_outputs()?.into_iter().filter(|o| o.active).map(|o| o.name).collect();
+    let workspaces = conn.get_workspaces()?;
+    let mut cmds = Vec::new();
+    let mut seen = HashSet::new();
+    for w in &snap.windows {
+        if !outputs.contains(&w.output) || !seen.insert(ws_target(w)) {
+            continue;
+        }
+        let misplaced = workspaces
+            .iter()
+            .any(|ws| on_workspace(w, ws.num, &ws.name) && ws.output != w.output);
+        if misplaced {
+            cmds.push(format!("workspace {}; move workspace to output {}", ws_target(w), quote(&w.output)));
+        }
+    }
+    if cmds.is_empty() {
+        return Ok(());
+    }
+    if let Some(f) = workspaces.iter().find(|ws| ws.focused) {
+        cmds.push(format!("workspace {}", quote(&f.name)));
+    }
+    conn.run_command(cmds.join("; "))?;
+    Ok(())
+}
+
+fn launch(w: &Window) -> bool {
+    let Some((prog, args)) = w.command.split_first() else {
+        return false;
+    };
+    // Own process group: a Ctrl-C in the terminal that ran `restore` must
+    // not take the apps down with it
+    let spawned = Cmd::new(prog)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+    if let Err(e) = &spawned {
+        eprintln!("i3-helper session: failed to launch {prog}: {e}");
+    }
+    spawned.is_ok()
+}
+
+fn restore() -> Result<()> {
+    let mut conn = wm::connect()?;
+    let snap = pick(wm::socket().as_deref())?;
+
+    // Subscribe before launching anything so no new window is missed
+    let events = wm::connect()?
+        .subscribe([EventType::Window])
+        .context("Failed to subscribe to window events")?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events {
+            match event {
+                Ok(Event::Window(w)) if w.change == WindowChange::New => {
+                    if tx.send(w.container).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    });
+
+    let tree = conn.get_tree().context("get_tree failed")?;
+    let plan = plan(&snap.windows, &capture(&tree));
+    if !plan.cmds.is_empty() {
+        conn.run_command(plan.cmds.join("; "))?;
+    }
+    let running = snap.windows.len() - plan.missing.len();
+
+    // One launch per recorded process: a browser's windows all come back
+    // from a single start
+    let mut pending = Vec::new();
+    let mut started = HashSet::new();
+    let mut launched = 0;
+    for &i in &plan.missing {
+        let w = &snap.windows[i];
+        if w.command.is_empty() {
+            eprintln!("i3-helper session: no command recorded for '{}'", w.title);
+            continue;
+        }
+        if w.pid.is_some_and(|pid| !started.insert(pid)) {
+            pending.push(w);
+            continue;
+        }
+        if launch(w) {
+            launched += 1;
+            pending.push(w);
+        }
+    }
+
+    let deadline = Instant::now() + WAIT;
+    while !pending.is_empty() {
+        let Ok(con) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) else {
+            break;
+        };
+        let Some(k) = pending.iter().position(|w| w.app() == node_app(&con)) else {
+            continue;
+        };
+        let w = pending.remove(k);
+        if let Some(c) = place(con.id, w, None) {
+            conn.run_command(c)?;
+        }
+    }
+
+    fix_outputs(&mut conn, &snap)?;
+    println!("session: {running} window(s) already running, {launched} app(s) launched");
+    if !pending.is_empty() {
+        println!("session: {} window(s) did not appear within {}s", pending.len(), WAIT.as_secs());
+    }
+    Ok(())
+}
+
+/// Snapshot the current session now (the daemon does this on its own).
+fn save() -> Result<()> {
+    let mut conn = wm::connect()?;
+    let tree = conn.get_tree().context("get_tree failed")?;
+    let mut snap = Snapshot {
+        socket: wm::socket(),
+        windows: capture(&tree).into_iter().map(|(_, w)| w).collect(),
+    };
+    write(&mut snap)?;
+    println!("saved {} window(s) to {}", snap.windows.len(), path().display());
+    Ok(())
+}
+
+/// `i3-helper session save|restore`
+pub fn run_client(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("save") if args.len() == 1 => save(),
+        Some("restore") if args.len() == 1 => restore(),
+        _ => {
+            eprintln!("Usage: i3-helper session save|restore");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{node, raw, x11};
+    use serde_json::{json, Value};
+
+    fn ws(id: i64, num: i32, nodes: Vec<Value>, floating: Vec<Value>) -> Value {
+        let mut v = raw(id, "workspace");
+        v["name"] = num.to_string().into();
+        v["num"] = num.into();
+        v["nodes"] = nodes.into();
+        v["floating_nodes"] = floating.into();
+        v
+    }
+
+    fn tree(workspaces: Vec<Value>) -> Node {
+        let mut content = raw(3, "con");
+        content["nodes"] = workspaces.into();
+        let mut output = raw(2, "output");
+        output["name"] = "eDP-1".into();
+        output["nodes"] = json!([content]);
+        let mut root = raw(1, "root");
+        root["nodes"] = json!([output]);
+        node(root)
+    }
+
+    fn session() -> Node {
+        let mut fc = raw(40, "floating_con");
+        fc["nodes"] = json!([x11(4, "mpv", "mpv", "video")]);
+        tree(vec![
+            ws(10, 1, vec![x11(1, "kitty", "kitty", "vim"), x11(2, "kitty", "kitty", "zsh")], vec![]),
+            ws(20, 2, vec![x11(3, "firefox", "firefox", "news")], vec![fc]),
+        ])
+    }
+
+    fn saved() -> Vec<Window> {
+        capture(&session()).into_iter().map(|(_, w)| w).collect()
+    }
+
+    #[test]
+    fn capture_records_workspace_output_and_floating() {
+        let w = saved();
+        assert_eq!(w.len(), 4);
+        assert_eq!((w[3].class.as_deref(), w[3].num, w[3].floating), (Some("mpv"), Some(2), true));
+        assert!(w.iter().all(|w| w.output == "eDP-1"));
+
+        // i3 sends no pid: it comes from the window's `_NET_WM_PID`
+        let mut win = x11(5, "kitty", "kitty", "zsh");
+        win["window"] = 0x5e0001.into();
+        crate::X11_PIDS.lock().unwrap().insert(0x5e0001, Some(4242));
+        let w = capture(&tree(vec![ws(10, 1, vec![win], vec![])]));
+        assert_eq!(w[0].1.pid, Some(4242));
+    }
+
+    #[test]
+    fn restore_is_idempotent() {
+        let p = plan(&saved(), &capture(&session()));
+        assert!(p.cmds.is_empty() && p.missing.is_empty(), "{p:?}");
+    }
+
+    #[test]
+    fn restore_moves_running_and_reports_missing() {
+        // After a crash: one kitty (titled "zsh") came back on workspace 3
+        let now = capture(&tree(vec![ws(30, 3, vec![x11(7, "kitty", "kitty", "zsh")], vec![])]));
+        let p = plan(&saved(), &now);
+        assert_eq!(p.cmds, ["[con_id=7] move container to workspace number 1"]);
+        // Title decided which kitty entry it stands in for
+        assert_eq!(p.missing, [0, 2, 3]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Spiral / dwindle (bspwm-style Fibonacci) layout.
+//!
+//!   ┌───────┬───────┐     k = windows already on the workspace
+//!   │       │   2   │     k=1 → split h, new goes right
+//!   │   1   ├───┬───┤     k=2 → split v, new goes down
+//!   │       │ 4 │ 3 │     k=3 → split h, new goes left  (spiral only)
+//!   └───────┴───┴───┘     k=4 → split v, new goes up    (spiral only)
+//!
+//! Every new window splits the most recently *created* window, never the
+//! focused one, so the result doesn't depend on where focus happened to be.
+//! Dwindle keeps new windows right/down; spiral rotates through quadrants.
+
+use crate::collect_tiling_leaves;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use swayipc::{Connection, Node};
+
+/// Temporary mark used as a `move container to mark` target.
+const MARK: &str = "_i3h_spiral";
+
+/// Most recently placed window per workspace (`ws_key` → con_id).
+static TAILS: Mutex<BTreeMap<String, i64>> = Mutex::new(BTreeMap::new());
+
+/// Follow a workspace that was renumbered (`dynamic`).
+pub fn rekey(old: &str, new: &str) {
+    let mut tails = TAILS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(id) = tails.remove(old) {
+        tails.insert(new.to_string(), id);
+    }
+}
+
+/// Place newly created window `new_id` on workspace `ws` (keyed `key`).
+pub fn place(cmd: &mut Connection, ws: &Node, key: &str, new_id: i64, rotate: bool) -> Result<()> {
+    let mut tails = TAILS.lock().unwrap_or_else(|e| e.into_inner());
+    let c = plan(&mut tails, ws, key, new_id, rotate);
+    drop(tails);
+    if let Some(c) = c {
+        cmd.run_command(c)?;
+    }
+    Ok(())
+}
+
+/// The command placing `new_id`, recording it as the workspace's tail.
+fn plan(
+    tails: &mut BTreeMap<String, i64>,
+    ws: &Node,
+    key: &str,
+    new_id: i64,
+    rotate: bool,
+) -> Option<String> {
+    let mut leaves = Vec::new();
+    collect_tiling_leaves(ws, &mut leaves);
+    // Floating windows aren't in `nodes`; leave them alone.
+    if !leaves.iter().any(|l| l.id == new_id) {
+        return None;
+    }
+    leaves.retain(|l| l.id != new_id);
+
+    // Fall back to the last leaf in tree order when the tail was closed,
+    // moved away, or predates the daemon.
+    let tail = tails
+        .get(key)
+        .and_then(|&id| leaves.iter().find(|l| l.id == id))
+        .or(leaves.last())
+        .map(|l| l.id);
+    tails.insert(key.to_string(), new_id);
+
+    // First window: nothing to split
+    let tail = tail?;
+
+    let k = leaves.len();
+    let (split, back) = if k % 2 == 1 { ("h", "left") } else { ("v", "up") };
+    // Quadrants 3 and 4 of each turn put the new window before the tail
+    let before = rotate && (k - 1) % 4 >= 2;
+
+    let mut c = format!(
+        "[con_id={tail}] split {split}; [con_id={tail}] mark --add {MARK}; \
+         [con_id={new_id}] move container to mark {MARK}; unmark {MARK}"
+    );
+    if before {
+        c.push_str(&format!("; [con_id={new_id}] move {back}"));
+    }
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{leaf, workspace};
+
+    fn ws(ids: &[i64]) -> Node {
+        workspace("splith", ids.iter().map(|&id| leaf(id, 0, 0, 100, 100)).collect())
+    }
+
+    fn split_of(c: &str, tail: i64, split: &str, new_id: i64) -> bool {
+        c.starts_with(&format!("[con_id={tail}] split {split}; [con_id={tail}] mark"))
+            && c.contains(&format!("[con_id={new_id}] move container to mark"))
+    }
+
+    #[test]
+    fn spiral_rotates_through_quadrants() {
+        let mut tails = BTreeMap::new();
+        assert_eq!(plan(&mut tails, &ws(&[1]), "1", 1, true), None);
+
+        let c = plan(&mut tails, &ws(&[1, 2]), "1", 2, true).unwrap();
+        assert!(split_of(&c, 1, "h", 2) && !c.contains("move left"), "{c}");
+        let c = plan(&mut tails, &ws(&[1, 2, 3]), "1", 3, true).unwrap();
+        assert!(split_of(&c, 2, "v", 3) && !c.contains("move up"), "{c}");
+        let c = plan(&mut tails, &ws(&[1, 2, 3, 4]), "1", 4, true).unwrap();
+        assert!(split_of(&c, 3, "h", 4) && c.ends_with("[con_id=4] move left"), "{c}");
+        let c = plan(&mut tails, &ws(&[1, 2, 3, 4, 5]), "1", 5, true).unwrap();
+        assert!(split_of(&c, 4, "v", 5) && c.ends_with("[con_id=5] move up"), "{c}");
+    }
+
+    #[test]
+    fn dwindle_stays_right_and_down() {
+        let mut tails = BTreeMap::new();
+        tails.insert("1".to_string(), 3);
+        let c = plan(&mut tails, &ws(&[1, 2, 3, 4]), "1", 4, false).unwrap();
+        assert!(split_of(&c, 3, "h", 4) && !c.contains("move left"), "{c}");
+        assert_eq!(tails["1"], 4);
+    }
+
+    #[test]
+    fn closed_tail_falls_back_to_last_leaf() {
+        let mut tails = BTreeMap::new();
+        tails.insert("1".to_string(), 9);
+        let c = plan(&mut tails, &ws(&[1, 2, 3]), "1", 3, false).unwrap();
+        assert!(split_of(&c, 2, "v", 3), "{c}");
+        // Floating windows aren't placed
+        assert_eq!(plan(&mut tails, &ws(&[1, 2]), "1", 7, false), None);
+    }
+}
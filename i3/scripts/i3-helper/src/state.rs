@@ -0,0 +1,112 @@
+//! Daemon state that survives restarts.
+//!
+//! `exec_always` restarts the daemon on every i3 reload, so modes would
+//! otherwise snap back to `--mode` each time. The mode table is written to
+//! `$XDG_STATE_HOME/i3-helper/state.json` after every change and read back
+//! at startup; `--reset-state` discards it. The focus history and saved
+//! marks ride along, written whenever the modes are and on shutdown.
+
+use crate::marks::{self, Criteria};
+use crate::{mode_label, modes, mru, parse_mode, Modes};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::{env, fs, io};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct State {
+    /// Global default mode (label, e.g. "vertical").
+    default: Option<String>,
+    /// Per-workspace mode overrides, keyed by `ws_key`.
+    workspaces: BTreeMap<String, String>,
+    /// Per-workspace master-area size.
+    nmaster: BTreeMap<String, usize>,
+    /// Focus history, newest first (see `mru`).
+    mru: Vec<i64>,
+    /// Mark → the window it belongs to (see `marks`).
+    marks: BTreeMap<String, Criteria>,
+}
+
+/// Serializes writers (event loop, signal and control threads).
+static SAVE: Mutex<()> = Mutex::new(());
+
+pub fn path() -> PathBuf {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    base.join("i3-helper").join("state.json")
+}
+
+/// Load saved state into `m`. A missing file is not an error; entries with
+/// unknown mode labels (from a newer/older build) are skipped.
+pub fn restore(m: &mut Modes) -> Result<()> {
+    let path = path();
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let state: State = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    if let Some(mode) = state.default.as_deref().and_then(parse_mode) {
+        m.default = mode;
+    }
+    m.per_ws = state
+        .workspaces
+        .iter()
+        .filter_map(|(ws, label)| Some((ws.clone(), parse_mode(label)?)))
+        .collect();
+    m.nmaster = state.nmaster;
+    mru::restore(state.mru);
+    marks::restore(state.marks);
+    Ok(())
+}
+
+fn write(state: &State) -> Result<()> {
+    let path = path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Write-then-rename so a crash never leaves a truncated file behind
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Snapshot the mode table to disk. Must not be called with `modes()` held.
+pub fn save() {
+    let _guard = SAVE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = {
+        let m = modes();
+        State {
+            default: Some(mode_label(m.default).to_string()),
+            workspaces: m
+                .per_ws
+                .iter()
+                .map(|(ws, &mode)| (ws.clone(), mode_label(mode).to_string()))
+                .collect(),
+            nmaster: m.nmaster.clone(),
+            mru: mru::snapshot(),
+            marks: marks::snapshot(),
+        }
+    };
+    if let Err(e) = write(&state) {
+        eprintln!("i3-helper: state not saved: {e:#}");
+    }
+}
+
+/// Forget saved state (`--reset-state`).
+pub fn reset() -> Result<()> {
+    let path = path();
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
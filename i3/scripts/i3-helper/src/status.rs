@@ -0,0 +1,310 @@
+//! Streaming status for bars: `i3-helper status [--follow] [--format F]`.
+//!
+//! The daemon keeps a snapshot of what a bar shows — the focused
+//! workspace's mode plus every workspace's label, mode and urgency — and
+//! pushes it to `follow` subscribers on the control socket whenever it
+//! changes. Subscribers get one JSON line per change; the client renders it
+//! for the bar:
+//!
+//!   json      the snapshot as-is
+//!   i3blocks  one plain text line: mode, focused workspace label, urgent
+//!             workspaces (also i3status-rust `persistent = true`)
+//!   waybar    `return-type: json` objects: text, alt, tooltip, class
+
+use crate::{collect_workspaces, ctl, mode_icon, mode_label, modes, parse_mode, ws_key};
+use crate::switch::plain;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use swayipc::Node;
+
+/// How often a following client retries while the daemon is down (it is
+/// restarted on every i3 reload).
+const RETRY: Duration = Duration::from_secs(1);
+
+/// Snapshots queued per subscriber; a bar this far behind is dropped.
+const BACKLOG: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workspace {
+    pub num: Option<i32>,
+    pub name: String,
+    pub focused: bool,
+    pub urgent: bool,
+    pub mode: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub mode: String,
+    pub workspace: String,
+    pub workspaces: Vec<Workspace>,
+}
+
+/// Tree-derived part of a workspace's entry.
+struct Seen {
+    key: String,
+    num: Option<i32>,
+    name: String,
+    urgent: bool,
+}
+
+static WORKSPACES: Mutex<Vec<Seen>> = Mutex::new(Vec::new());
+static LAST: Mutex<Option<Status>> = Mutex::new(None);
+/// Queues of the per-subscriber writer threads: a stalled bar never
+/// blocks the event loop, which publishes from its own thread.
+static SUBSCRIBERS: Mutex<Vec<SyncSender<String>>> = Mutex::new(Vec::new());
+
+// ── Daemon side ───────────────────────────────────────────────
+
+/// Current snapshot; modes are read live so a mode switch shows up without
+/// a tree refresh.
+pub fn snapshot() -> Status {
+    let workspaces = WORKSPACES.lock().unwrap_or_else(|e| e.into_inner());
+    let m = modes();
+    Status {
+        mode: mode_label(m.focused_mode()).to_string(),
+        workspace: m.focused.clone(),
+        workspaces: workspaces
+            .iter()
+            .map(|ws| Workspace {
+                num: ws.num,
+                name: ws.name.clone(),
+                focused: ws.key == m.focused,
+                urgent: ws.urgent,
+                mode: mode_label(m.get(&ws.key)).to_string(),
+            })
+            .collect(),
+    }
+}
+
+fn line(status: &Status) -> String {
+    let mut v = serde_json::to_value(status).unwrap_or_default();
+    v["ok"] = true.into();
+    let mut s = v.to_string();
+    s.push('\n');
+    s
+}
+
+/// Record workspaces from a fresh tree, then publish if anything changed.
+pub fn update(tree: &Node) {
+    let mut ws_buf = Vec::with_capacity(10);
+    collect_workspaces(tree, &mut ws_buf);
+    let list = ws_buf
+        .iter()
+        .map(|ws| Seen {
+            key: ws_key(ws),
+            num: ws.num,
+            name: ws.name.clone().unwrap_or_default(),
+            // i3 marks a workspace urgent while any window on it is
+            urgent: ws.urgent,
+        })
+        .collect();
+    *WORKSPACES.lock().unwrap_or_else(|e| e.into_inner()) = list;
+    publish();
+}
+
+/// Send the snapshot to every subscriber if it differs from the last one.
+/// Must not be called with `modes()` held.
+pub fn publish() {
+    let status = snapshot();
+    {
+        let mut last = LAST.lock().unwrap_or_else(|e| e.into_inner());
+        if last.as_ref() == Some(&status) {
+            return;
+        }
+        *last = Some(status.clone());
+    }
+    let msg = line(&status);
+    let mut subs = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+    // A bar that went away (its writer exited) or fell behind is dropped
+    subs.retain(|tx| tx.try_send(msg.clone()).is_ok());
+}
+
+/// Hand a control connection over to the subscriber list.
+pub fn subscribe(stream: UnixStream) -> Result<()> {
+    let (tx, rx) = mpsc::sync_channel(BACKLOG);
+    tx.try_send(line(&snapshot()))?;
+    thread::Builder::new()
+        .name("status-subscriber".into())
+        .spawn(move || forward(stream, rx))
+        .context("failed to spawn status writer")?;
+    SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner()).push(tx);
+    Ok(())
+}
+
+/// Writer thread: forward queued lines until the bar or the queue goes away.
+fn forward(mut stream: UnixStream, rx: Receiver<String>) {
+    for msg in rx {
+        if stream.write_all(msg.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// One-shot reply for `status` without `--follow`.
+pub fn reply() -> Value {
+    let mut v = serde_json::to_value(snapshot()).unwrap_or_else(|_| json!({}));
+    v["ok"] = true.into();
+    v
+}
+
+// ── Client side ───────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Json,
+    I3blocks,
+    Waybar,
+}
+
+fn icon(mode: &str) -> &'static str {
+    parse_mode(mode).map(mode_icon).unwrap_or("? Unknown")
+}
+
+fn urgent_names(s: &Status) -> Vec<&str> {
+    s.workspaces.iter().filter(|w| w.urgent).map(|w| w.name.as_str()).collect()
+}
+
+fn render(s: &Status, format: Format) -> String {
+    match format {
+        Format::Json => serde_json::to_string(s).unwrap_or_default(),
+        Format::I3blocks => {
+            let focused = s.workspaces.iter().find(|w| w.focused);
+            let mut text = match focused {
+                Some(w) => format!("{}  {}", icon(&s.mode), plain(&w.name)),
+                None => icon(&s.mode).to_string(),
+            };
+            // Names carry Pango spans; i3blocks shows text as-is by default
+            let urgent: Vec<String> = urgent_names(s).into_iter().map(plain).collect();
+            if !urgent.is_empty() {
+                text.push_str(&format!("  ⚠ {}", urgent.join(" ")));
+            }
+            text
+        }
+        Format::Waybar => {
+            let tooltip: Vec<String> = s
+                .workspaces
+                .iter()
+                .map(|w| {
+                    let mark = if w.focused { "▸ " } else if w.urgent { "! " } else { "  " };
+                    format!("{mark}{}  ({})", w.name, w.mode)
+                })
+                .collect();
+            let mut class = vec![format!("mode-{}", s.mode)];
+            if !urgent_names(s).is_empty() {
+                class.push("urgent".into());
+            }
+            json!({
+                "text": icon(&s.mode),
+                "alt": s.mode,
+                "tooltip": tooltip.join("\n"),
+                "class": class,
+            })
+            .to_string()
+        }
+    }
+}
+
+/// Print status lines from one daemon connection until it closes.
+fn stream(follow: bool, format: Format) -> Result<()> {
+    let mut conn = ctl::connect()?;
+    let req = json!({ "cmd": "status", "follow": follow });
+    writeln!(conn, "{req}").context("Failed to send request")?;
+
+    let stdout = std::io::stdout();
+    for l in BufReader::new(conn).lines() {
+        let reply: Value = serde_json::from_str(&l?).context("Malformed status from daemon")?;
+        if reply["ok"].as_bool() != Some(true) {
+            bail!("{}", reply["error"].as_str().unwrap_or("request failed"));
+        }
+        let status: Status = serde_json::from_value(reply).context("Malformed status from daemon")?;
+        let mut out = stdout.lock();
+        writeln!(out, "{}", render(&status, format))?;
+        out.flush()?;
+        if !follow {
+            return Ok(());
+        }
+    }
+    bail!("daemon closed the connection")
+}
+
+/// `i3-helper status [--follow] [--format json|i3blocks|waybar]`
+pub fn run_client(args: &[String]) -> Result<()> {
+    let (mut follow, mut format) = (false, Format::Json);
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--follow" | "-f" => follow = true,
+            "--format" => {
+                i += 1;
+                format = match args.get(i).map(String::as_str) {
+                    Some("json") => Format::Json,
+                    Some("i3blocks") => Format::I3blocks,
+                    Some("waybar") => Format::Waybar,
+                    _ => {
+                        eprintln!("i3-helper status: --format requires json|i3blocks|waybar");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            other => {
+                eprintln!("i3-helper status: unexpected argument '{other}'");
+                std::process::exit(1);
+            }
+        }
+        i += 1;
+    }
+
+    if !follow {
+        return stream(false, format);
+    }
+    // Bars keep this process for the whole session; ride out daemon restarts
+    loop {
+        if let Err(e) = stream(true, format) {
+            eprintln!("i3-helper status: {e:#}");
+        }
+        thread::sleep(RETRY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ws(num: i32, name: &str, focused: bool, urgent: bool) -> Workspace {
+        let mode = "alternating".to_string();
+        Workspace { num: Some(num), name: name.into(), focused, urgent, mode }
+    }
+
+    #[test]
+    fn i3blocks_shows_focused_label_and_urgent() {
+        let mut s = Status {
+            mode: "master".into(),
+            workspace: "2".into(),
+            workspaces: vec![ws(1, "1: ", false, false), ws(2, "2: ", true, false)],
+        };
+        assert_eq!(render(&s, Format::I3blocks), "◧ Master  2:");
+        s.workspaces[0].urgent = true;
+        assert_eq!(render(&s, Format::I3blocks), "◧ Master  2:  ⚠ 1:");
+    }
+
+    #[test]
+    fn i3blocks_strips_workspace_markup() {
+        let s = Status {
+            mode: "master".into(),
+            workspace: "3".into(),
+            workspaces: vec![
+                ws(3, "3: <span size='x-large'>R&amp;D </span>", true, false),
+                ws(4, "4:<span foreground='red'>mail</span>", false, true),
+            ],
+        };
+        assert_eq!(render(&s, Format::I3blocks), "◧ Master  3: R&D  ⚠ 4:mail");
+    }
+}
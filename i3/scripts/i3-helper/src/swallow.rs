@@ -0,0 +1,325 @@
+//! Terminal swallowing: a window started from a terminal takes the
+//! terminal's tile until it closes.
+//!
+//! On `new`, the window's pid (`_NET_WM_PID` on i3, which leaves it out of
+//! the tree) is walked up the `/proc` parent chain; if a terminal window
+//! on the same workspace owns one of those processes, the two swap places
+//! and the terminal is moved to the scratchpad with a hidden
+//! `_i3h_swallow_<child>` mark. The marks are the state: they survive a
+//! daemon restart, and a terminal whose child is gone comes back where the
+//! child was (onto the focused workspace if that is unknown, e.g. the child
+//! closed while the daemon was down).
+//!
+//! Configured in `[swallow]`: `terminals`, plus `allow`/`deny` lists of
+//! child classes (lowercased, as in `[icons]`).
+
+use crate::config::Swallow;
+use crate::tiling::{find_tiling_parent, Commands};
+use crate::{collect_tiling_leaves, window_class, window_pid, workspace_of};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use swayipc::{Node, NodeType};
+
+const MARK: &str = "_i3h_swallow_";
+/// Temporary mark for moving a terminal back next to a sibling.
+const ANCHOR_MARK: &str = "_i3h_unswallow";
+
+/// Where a swallowing child sits, for putting the terminal back.
+#[derive(Debug, Clone, PartialEq)]
+enum Anchor {
+    After(i64),
+    Before(i64),
+    /// Alone on the workspace (`workspace` argument).
+    Workspace(String),
+}
+
+/// child → (terminal, child's position), as of the last tree.
+static PAIRS: Mutex<BTreeMap<i64, (i64, Anchor)>> = Mutex::new(BTreeMap::new());
+
+/// Parent pid from the contents of `/proc/<pid>/stat`. The command name
+/// may contain spaces and parentheses, so fields are counted from the last
+/// `)`.
+fn parse_ppid(stat: &str) -> Option<i32> {
+    stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Ancestors of `pid`, nearest first.
+fn ancestors(mut pid: i32) -> Vec<i32> {
+    let mut chain = Vec::new();
+    while pid > 1 && chain.len() < 64 {
+        match fs::read_to_string(format!("/proc/{pid}/stat")).ok().as_deref().and_then(parse_ppid) {
+            Some(ppid) if ppid > 0 => {
+                chain.push(ppid);
+                pid = ppid;
+            }
+            _ => break,
+        }
+    }
+    chain
+}
+
+#[inline]
+fn listed(list: &[String], class: &str) -> bool {
+    list.iter().any(|c| c.eq_ignore_ascii_case(class))
+}
+
+/// The terminal window that started `child`: the one whose process is the
+/// nearest ancestor. Single-instance terminals (kitty) own many windows
+/// with one pid; then the one focused before `child` appeared wins.
+fn pick_terminal<'a>(
+    leaves: &[&'a Node],
+    child: &Node,
+    chain: &[i32],
+    cfg: &Swallow,
+    prev_focus: Option<i64>,
+) -> Option<&'a Node> {
+    let terms: Vec<&Node> = leaves
+        .iter()
+        .copied()
+        .filter(|n| n.id != child.id)
+        .filter(|n| window_class(n).is_some_and(|c| listed(&cfg.terminals, &c)))
+        .collect();
+    // Cached per window: only the first lookup runs `xprop`
+    let pid = chain.iter().find(|p| terms.iter().any(|n| window_pid(n) == Some(**p)))?;
+    let mut owned = terms.into_iter().filter(|n| window_pid(n) == Some(*pid)).peekable();
+    let first = *owned.peek()?;
+    Some(owned.find(|n| Some(n.id) == prev_focus).unwrap_or(first))
+}
+
+/// Swallow new window `con_id` into the terminal that launched it, if any.
+pub fn on_new(
+    cmd: &mut impl Commands,
+    tree: &Node,
+    con_id: i64,
+    prev_focus: Option<i64>,
+    cfg: &Swallow,
+) -> Result<()> {
+    if !cfg.enabled {
+        return Ok(());
+    }
+    let ws = match workspace_of(tree, con_id) {
+        Some(ws) => ws,
+        None => return Ok(()),
+    };
+    // Tiling only: floating children (dialogs, pickers) don't take a tile
+    let mut leaves = Vec::new();
+    collect_tiling_leaves(ws, &mut leaves);
+    let child = match leaves.iter().find(|n| n.id == con_id) {
+        Some(c) => *c,
+        None => return Ok(()),
+    };
+    let class = match window_class(child) {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    if listed(&cfg.terminals, &class)
+        || listed(&cfg.deny, &class)
+        || (!cfg.allow.is_empty() && !listed(&cfg.allow, &class))
+    {
+        return Ok(());
+    }
+    let chain = match window_pid(child) {
+        Some(pid) => ancestors(pid),
+        None => return Ok(()),
+    };
+    let term = match pick_terminal(&leaves, child, &chain, cfg, prev_focus) {
+        Some(t) => t.id,
+        None => return Ok(()),
+    };
+
+    cmd.run(&format!(
+        "[con_id={con_id}] swap container with con_id {term}; \
+         [con_id={term}] mark --add {MARK}{con_id}; \
+         [con_id={term}] move scratchpad; \
+         [con_id={con_id}] focus"
+    ))
+}
+
+fn ws_target(ws: &Node) -> String {
+    match ws.num {
+        Some(n) if n >= 0 => format!("number {n}"),
+        _ => format!("\"{}\"", ws.name.as_deref().unwrap_or_default().replace('"', "\\\"")),
+    }
+}
+
+fn contains(node: &Node, id: i64) -> bool {
+    node.id == id || node.nodes.iter().chain(node.floating_nodes.iter()).any(|c| contains(c, id))
+}
+
+/// Position of tiling container `id`, relative to something that outlives
+/// it. A split container that would be left empty goes away with it, so
+/// an only child is anchored by its parent.
+fn anchor(tree: &Node, id: i64) -> Option<Anchor> {
+    let parent = find_tiling_parent(tree, id)?;
+    let idx = parent.nodes.iter().position(|c| c.id == id)?;
+    if idx > 0 {
+        return Some(Anchor::After(parent.nodes[idx - 1].id));
+    }
+    if let Some(next) = parent.nodes.get(1) {
+        return Some(Anchor::Before(next.id));
+    }
+    if parent.node_type == NodeType::Workspace {
+        return Some(Anchor::Workspace(ws_target(parent)));
+    }
+    anchor(tree, parent.id)
+}
+
+/// Bring terminal `term` back from the scratchpad, at `at` if known.
+fn unswallow(term: i64, child: i64, at: Option<&Anchor>) -> String {
+    let mut c = format!(
+        "[con_id={term}] unmark {MARK}{child}; \
+         [con_id={term}] scratchpad show; \
+         [con_id={term}] floating disable"
+    );
+    match at {
+        Some(Anchor::After(sib)) | Some(Anchor::Before(sib)) => {
+            c.push_str(&format!(
+                "; [con_id={sib}] mark --add {ANCHOR_MARK}; \
+                 [con_id={term}] move container to mark {ANCHOR_MARK}; \
+                 unmark {ANCHOR_MARK}"
+            ));
+            // `move to mark` always inserts after the marked container
+            if let Some(Anchor::Before(sib)) = at {
+                c.push_str(&format!("; [con_id={term}] swap container with con_id {sib}"));
+            }
+        }
+        Some(Anchor::Workspace(ws)) => {
+            c.push_str(&format!("; [con_id={term}] move container to workspace {ws}"));
+        }
+        None => {}
+    }
+    c.push_str(&format!("; [con_id={term}] focus"));
+    c
+}
+
+/// Whether `node` is a terminal parked in the scratchpad by a child.
+pub fn is_swallowed(node: &Node) -> bool {
+    node.marks.iter().any(|m| m.starts_with(MARK))
+}
+
+/// (terminal, child) for every swallow mark in the tree.
+fn collect_marked(node: &Node, out: &mut Vec<(i64, i64)>) {
+    for m in &node.marks {
+        if let Some(child) = m.strip_prefix(MARK).and_then(|c| c.parse().ok()) {
+            out.push((node.id, child));
+        }
+    }
+    for c in node.nodes.iter().chain(node.floating_nodes.iter()) {
+        collect_marked(c, out);
+    }
+}
+
+/// Track swallowing children in a fresh tree and restore the terminals of
+/// those that are gone. Runs on every tree, whether or not swallowing is
+/// enabled, so turning it off never strands a terminal.
+pub fn refresh(cmd: &mut impl Commands, tree: &Node) -> Result<()> {
+    let mut marked = Vec::new();
+    collect_marked(tree, &mut marked);
+
+    let mut pairs = PAIRS.lock().unwrap_or_else(|e| e.into_inner());
+    let prev = std::mem::take(&mut *pairs);
+    let mut next = BTreeMap::new();
+    let mut cmds = Vec::new();
+    for (term, child) in marked {
+        let last = prev.get(&child).map(|(_, a)| a);
+        if contains(tree, child) {
+            // Keep the last tiling position while the child floats
+            if let Some(a) = anchor(tree, child).or_else(|| last.cloned()) {
+                next.insert(child, (term, a));
+            }
+        } else {
+            cmds.push(unswallow(term, child, last));
+        }
+    }
+    *pairs = next;
+    drop(pairs);
+
+    if cmds.is_empty() {
+        return Ok(());
+    }
+    cmd.run(&cmds.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{leaf, node, split, tree, workspace, x11};
+    use std::process;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Commands for Recorder {
+        fn run(&mut self, cmd: &str) -> Result<()> {
+            self.0.push(cmd.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn i3_windows_are_matched_by_net_wm_pid() {
+        // i3 sends no pids: this test process plays the child, its parent
+        // the terminal, both known only through their X11 windows
+        let (me, parent) = (process::id() as i32, std::os::unix::process::parent_id() as i32);
+        let mut term = x11(1, "kitty", "kitty", "zsh");
+        term["window"] = 0x5a0001.into();
+        let mut child = x11(2, "mpv", "gl", "video");
+        child["window"] = 0x5a0002.into();
+        let mut pids = crate::X11_PIDS.lock().unwrap();
+        pids.insert(0x5a0001, Some(parent));
+        pids.insert(0x5a0002, Some(me));
+        drop(pids);
+
+        let t = tree(workspace("splith", vec![term, child]));
+        let cfg = Swallow { enabled: true, ..Swallow::default() };
+        let mut rec = Recorder::default();
+        on_new(&mut rec, &t, 2, Some(1), &cfg).unwrap();
+        assert_eq!(rec.0.len(), 1);
+        assert!(rec.0[0].starts_with("[con_id=2] swap container with con_id 1;"), "{:?}", rec.0);
+    }
+
+    #[test]
+    fn ppid_survives_odd_command_names() {
+        assert_eq!(parse_ppid("4242 (zsh) S 4100 4242 4242 34816"), Some(4100));
+        assert_eq!(parse_ppid("77 (a) b (c)) R 12 77 77 0"), Some(12));
+        assert_eq!(parse_ppid("garbage"), None);
+    }
+
+    #[test]
+    fn anchors_outlive_the_child() {
+        // ws: [1, split(2, 3)]
+        let ws = workspace("splith", vec![leaf(1, 0, 0, 400, 600), split("splitv", vec![
+            leaf(2, 400, 0, 400, 300),
+            leaf(3, 400, 300, 400, 300),
+        ])]);
+        assert_eq!(anchor(&ws, 3), Some(Anchor::After(2)));
+        assert_eq!(anchor(&ws, 2), Some(Anchor::Before(3)));
+        assert_eq!(anchor(&ws, 1), Some(Anchor::Before(100)));
+
+        // An only child in a split container is anchored by the container
+        let ws = workspace("splith", vec![leaf(1, 0, 0, 400, 600), split("splitv", vec![leaf(2, 400, 0, 400, 600)])]);
+        assert_eq!(anchor(&ws, 2), Some(Anchor::After(1)));
+
+        let alone = workspace("splith", vec![leaf(1, 0, 0, 800, 600)]);
+        assert_eq!(anchor(&alone, 1), Some(Anchor::Workspace("number 1".into())));
+    }
+
+    #[test]
+    fn terminal_returns_in_front_of_the_next_sibling() {
+        let c = unswallow(7, 9, Some(&Anchor::Before(3)));
+        assert!(c.starts_with("[con_id=7] unmark _i3h_swallow_9; [con_id=7] scratchpad show"));
+        assert!(c.contains("[con_id=3] mark --add _i3h_unswallow; [con_id=7] move container to mark _i3h_unswallow"));
+        assert!(c.ends_with("[con_id=7] swap container with con_id 3; [con_id=7] focus"));
+    }
+
+    #[test]
+    fn marks_are_the_state() {
+        let mut term = leaf(7, 0, 0, 800, 600);
+        term["marks"] = serde_json::json!(["keep", "_i3h_swallow_9"]);
+        let mut out = Vec::new();
+        collect_marked(&node(term), &mut out);
+        assert_eq!(out, [(7, 9)]);
+    }
+}
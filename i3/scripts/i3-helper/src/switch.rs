@@ -0,0 +1,266 @@
+//! Window switcher: `i3-helper switch`.
+//!
+//! Lists every window — scratchpad included — as `workspace icon class —
+//! title`, most recently focused first (from the daemon's `mru` history;
+//! tree order when it isn't running), pipes the list through a dmenu-style
+//! menu and focuses the pick. The focused window goes last, so Enter on the
+//! first entry behaves like alt-tab. Identical lines (two kitty windows
+//! titled "zsh") are numbered so each still picks its own window.
+//!
+//! The menu is `switch.command` (run by `sh -c`, default: the themed dmenu
+//! from `dmenu-theme.sh`). If it can't be run — any failure other than a
+//! silent exit 1 with no pick, which is how dmenu and rofi report Escape —
+//! `rofi -dmenu` and plain `dmenu` are tried before giving up with a
+//! notification.
+
+use crate::ctl::{self, Request};
+use crate::{collect_leaves, config, find_focused, leaf_icon, swallow, window_class, wm};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command as Cmd, Stdio};
+use swayipc::{Node, NodeType};
+
+/// Tried in order after the configured menu; `{}` is the prompt.
+const FALLBACKS: &[&str] = &["rofi -dmenu -i -p {}", "dmenu -i -l 15 -p {}"];
+
+/// Every workspace, the scratchpad included.
+fn all_workspaces<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
+    if node.node_type == NodeType::Workspace {
+        out.push(node);
+        return;
+    }
+    for child in &node.nodes {
+        all_workspaces(child, out);
+    }
+}
+
+/// Icon markup reduced to the glyph; dmenu can't render Pango.
+pub(crate) fn plain(markup: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in markup.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    let out = out.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+    out.trim().to_string()
+}
+
+/// Menu line for `leaf` on `ws`.
+fn entry(ws: &Node, leaf: &Node, cfg: &config::Config) -> String {
+    let label = match ws.num {
+        Some(n) if n >= 0 => n.to_string(),
+        _ if ws.name.as_deref() == Some("__i3_scratch") => "scratch".to_string(),
+        _ => ws.name.clone().unwrap_or_default(),
+    };
+    let class = window_class(leaf).unwrap_or_default();
+    let (key, icon) = leaf_icon(leaf, cfg);
+    // Unmapped classes come back as the bare name; don't print it twice
+    let icon = if *icon == key { String::new() } else { plain(&icon) };
+    let title = leaf.name.as_deref().unwrap_or_default();
+    format!("{label} {icon} {class} — {title}")
+}
+
+/// Windows in switcher order with their menu lines.
+fn entries(tree: &Node, mru: &[i64], cfg: &config::Config) -> Vec<(i64, String)> {
+    let mut ws_buf = Vec::new();
+    all_workspaces(tree, &mut ws_buf);
+    let mut list = Vec::new();
+    let mut leaves = Vec::new();
+    for ws in ws_buf {
+        leaves.clear();
+        collect_leaves(ws, &mut leaves);
+        for leaf in &leaves {
+            // Layout placeholders have no window; parked terminals return
+            // on their own
+            if (leaf.window.is_none() && leaf.app_id.is_none()) || swallow::is_swallowed(leaf) {
+                continue;
+            }
+            list.push((leaf.id, entry(ws, leaf, cfg)));
+        }
+    }
+
+    // Stable sort: history order first, the rest in tree order
+    let focused = find_focused(tree).map(|f| f.id);
+    list.sort_by_key(|(id, _)| {
+        let rank = mru.iter().position(|m| m == id).unwrap_or(usize::MAX);
+        (Some(*id) == focused, rank)
+    });
+
+    // The pick is matched back by its text
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (_, line) in &mut list {
+        let n = seen.entry(line.clone()).or_default();
+        *n += 1;
+        if *n > 1 {
+            line.push_str(&format!(" ({n})"));
+        }
+    }
+    list
+}
+
+/// Focus history from the daemon, newest first; empty if it isn't running.
+fn mru() -> Vec<i64> {
+    ctl::send(&Request::ListMru)
+        .ok()
+        .and_then(|reply| {
+            let windows = reply["windows"].as_array()?.iter();
+            Some(windows.filter_map(|w| w["id"].as_i64()).collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Run `menu` on `input`. `None` when it could not do its job (missing
+/// program or script, display error); `Some("")` when cancelled.
+fn run_menu(menu: &str, input: &str) -> Result<Option<String>> {
+    let mut child = match Cmd::new("sh")
+        .args(["-c", menu])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(_) => return Ok(None),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // A menu that died on startup closes the pipe; its status says why
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let out = child.wait_with_output().context("menu failed")?;
+    let pick = String::from_utf8_lossy(&out.stdout).trim_end_matches('\n').to_string();
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    match out.status.code() {
+        Some(0) => Ok(Some(pick)),
+        Some(1) if pick.is_empty() && stderr.trim().is_empty() => Ok(Some(pick)),
+        _ => {
+            eprintln!("i3-helper: menu '{menu}' failed ({}): {}", out.status, stderr.trim());
+            Ok(None)
+        }
+    }
+}
+
+/// First of `menus` that runs; `None` when none does.
+fn choose_from(menus: impl IntoIterator<Item = String>, input: &str) -> Result<Option<String>> {
+    for m in menus {
+        if let Some(c) = run_menu(&m, input)? {
+            return Ok(Some(c));
+        }
+    }
+    Ok(None)
+}
+
+/// Pick a line of `input` with `menu`, falling back to rofi and dmenu
+/// (with `prompt`). Empty when cancelled.
+pub fn choose(menu: &str, prompt: &str, input: &str) -> Result<String> {
+    let fallbacks = FALLBACKS.iter().map(|f| f.replace("{}", prompt));
+    if let Some(c) = choose_from(std::iter::once(menu.to_string()).chain(fallbacks), input)? {
+        return Ok(c);
+    }
+    let _ = Cmd::new("notify-send")
+        .args(["-t", "2000", "i3-helper", "no menu program (dmenu/rofi) found"])
+        .status();
+    bail!("no menu program found (configured command, rofi, dmenu)");
+}
+
+/// `i3-helper switch`
+pub fn run_client(args: &[String]) -> Result<()> {
+    if let Some(a) = args.first() {
+        eprintln!("i3-helper switch: unexpected argument '{a}'");
+        std::process::exit(1);
+    }
+    let mut conn = wm::connect()?;
+    let tree = conn.get_tree().context("get_tree failed")?;
+    let cfg = config::get();
+    let list = entries(&tree, &mru(), &cfg);
+    if list.is_empty() {
+        return Ok(());
+    }
+    let input: String = list.iter().map(|(_, line)| format!("{line}\n")).collect();
+
+    let choice = choose(&cfg.switch.command, "window", &input)?;
+    // Cancelled (Escape): nothing to do
+    let id = match list.iter().find(|(_, line)| *line == choice) {
+        Some((id, _)) => *id,
+        None => return Ok(()),
+    };
+    for outcome in conn.run_command(format!("[con_id={id}] focus"))? {
+        outcome.context("focus failed")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{node, raw, x11};
+    use serde_json::json;
+
+    #[test]
+    fn mru_order_with_focused_last_and_scratchpad_listed() {
+        let mut ws = raw(10, "workspace");
+        ws["name"] = "1: x".into();
+        ws["num"] = 1.into();
+        ws["nodes"] = json!([x11(1, "kitty", "kitty", "zsh"), x11(2, "firefox", "firefox", "news")]);
+        let mut scratch = raw(20, "workspace");
+        scratch["name"] = "__i3_scratch".into();
+        scratch["num"] = (-1).into();
+        let mut fc = raw(30, "floating_con");
+        fc["nodes"] = json!([x11(3, "obsidian", "obsidian", "notes")]);
+        scratch["floating_nodes"] = json!([fc]);
+        let mut root = raw(0, "root");
+        root["nodes"] = json!([ws, scratch]);
+        root["nodes"][0]["nodes"][0]["focused"] = true.into();
+        root["focus"] = json!([10]);
+        root["nodes"][0]["focus"] = json!([1]);
+
+        let list = entries(&node(root), &[1, 3], &config::Config::builtin().unwrap());
+        let ids: Vec<i64> = list.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [3, 2, 1]);
+        assert!(list[0].1.starts_with("scratch "), "{}", list[0].1);
+        assert!(list[0].1.ends_with("obsidian — notes"), "{}", list[0].1);
+        assert_eq!(plain("<span size='x-large'>\u{E658} </span>"), "\u{E658}");
+        assert_eq!(plain("<span size='large'>R&amp;D &lt;x&gt; </span>"), "R&D <x>");
+    }
+
+    #[test]
+    fn identical_windows_get_distinct_lines() {
+        let mut ws = raw(10, "workspace");
+        ws["num"] = 1.into();
+        ws["nodes"] = json!([
+            x11(1, "kitty", "kitty", "zsh"),
+            x11(2, "kitty", "kitty", "zsh"),
+            x11(3, "kitty", "kitty", "zsh"),
+        ]);
+        let mut root = raw(0, "root");
+        root["nodes"] = json!([ws]);
+
+        let list = entries(&node(root), &[], &config::Config::builtin().unwrap());
+        let lines: Vec<&str> = list.iter().map(|(_, l)| l.as_str()).collect();
+        assert!(lines[1].ends_with("kitty — zsh (2)"), "{lines:?}");
+        assert!(lines[2].ends_with("kitty — zsh (3)"), "{lines:?}");
+        // Picking the second line focuses the second window
+        let pick = list.iter().find(|(_, l)| *l == lines[1]).map(|(id, _)| *id);
+        assert_eq!(pick, Some(2));
+    }
+
+    #[test]
+    fn unusable_menus_fall_back_and_escape_does_not() {
+        let menus = |m: &[&str]| m.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let input = "first\nsecond\n";
+        // A missing program or theme script: the next menu gets the list
+        let picked = choose_from(menus(&["i3-helper-no-such-menu", "head -n 1"]), input).unwrap();
+        assert_eq!(picked.as_deref(), Some("first"));
+        let picked = choose_from(menus(&["echo 'cannot open display' >&2; exit 1", "head -n 1"]), input).unwrap();
+        assert_eq!(picked.as_deref(), Some("first"));
+        // Escape: empty pick, exit 1, nothing said — no second menu
+        let picked = choose_from(menus(&["cat >/dev/null; exit 1", "head -n 1"]), input).unwrap();
+        assert_eq!(picked.as_deref(), Some(""));
+        assert_eq!(choose_from(menus(&["i3-helper-no-such-menu"]), input).unwrap(), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Builders for recorded i3 tree fragments used by unit tests.
+//!
+//! `Node` is `#[non_exhaustive]`, so fixtures are built from the same JSON
+//! shape `get_tree` returns; only the fields a test cares about vary.
+
+use serde_json::{json, Value};
+use swayipc::Node;
+
+/// A node of `node_type` with every required field present.
+pub fn raw(id: i64, node_type: &str) -> Value {
+    json!({
+        "id": id,
+        "name": null,
+        "type": node_type,
+        "border": "normal",
+        "current_border_width": 2,
+        "layout": "splith",
+        "percent": null,
+        "rect": { "x": 0, "y": 0, "width": 800, "height": 600 },
+        "window_rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+        "deco_rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+        "geometry": { "x": 0, "y": 0, "width": 0, "height": 0 },
+        "urgent": false,
+        "focused": false,
+        "focus": [],
+        "nodes": [],
+        "floating_nodes": [],
+        "sticky": false,
+    })
+}
+
+pub fn node(v: Value) -> Node {
+    serde_json::from_value(v).expect("fixture matches swayipc's Node")
+}
+
+/// An X11 leaf window with window id `0x1200000 + id`.
+pub fn x11(id: i64, class: &str, instance: &str, title: &str) -> Value {
+    let mut v = raw(id, "con");
+    v["name"] = title.into();
+    v["window"] = (0x1200000 + id).into();
+    v["window_properties"] = json!({ "class": class, "instance": instance, "title": title });
+    v
+}
+
+/// An X11 leaf window (id 1).
+pub fn window(class: &str, instance: &str, title: &str) -> Node {
+    node(x11(1, class, instance, title))
+}
+
+/// A tiling leaf window at the given position and size.
+pub fn leaf(id: i64, x: i32, y: i32, width: i32, height: i32) -> Value {
+    let mut v = raw(id, "con");
+    v["rect"] = json!({ "x": x, "y": y, "width": width, "height": height });
+    v["window"] = (0x1200000 + id).into();
+    v
+}
+
+/// A split container (id 100) holding `children`.
+pub fn split(layout: &str, children: Vec<Value>) -> Value {
+    let mut v = raw(100, "con");
+    v["layout"] = layout.into();
+    v["nodes"] = children.into();
+    v
+}
+
+/// Workspace "1" (id 10) holding `children`.
+pub fn workspace(layout: &str, children: Vec<Value>) -> Node {
+    let mut v = raw(10, "workspace");
+    v["name"] = "1".into();
+    v["num"] = 1.into();
+    v["layout"] = layout.into();
+    v["nodes"] = children.into();
+    node(v)
+}
+
+/// `ws` under a root and output, as `get_tree` returns it.
+pub fn tree(ws: Node) -> Node {
+    let mut output = raw(1001, "output");
+    output["name"] = "eDP-1".into();
+    output["nodes"] = json!([ws]);
+    let mut root = raw(1000, "root");
+    root["nodes"] = json!([output]);
+    node(root)
+}
+
+/// A native Wayland leaf window (Sway): `app_id`, no X11 properties.
+pub fn wayland_window(app_id: &str, title: &str) -> Node {
+    let mut v = raw(1, "con");
+    v["name"] = title.into();
+    v["app_id"] = app_id.into();
+    v["shell"] = "xdg_shell".into();
+    node(v)
+}
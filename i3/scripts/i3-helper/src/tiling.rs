@@ -0,0 +1,345 @@
+//! Split decisions for the alternating, vertical and horizontal modes.
+//!
+//! Focus events only record which container is focused and how big it is;
+//! they never touch IPC. The decision is made on `WindowChange::New`, once
+//! the tree shows where i3 attached the new window: if its parent already
+//! splits the right way nothing is sent, otherwise a single command fixes
+//! it. Geometry is cached per container so the alternating mode can decide
+//! from the size the window had *before* i3 split it.
+
+use crate::{MODE_ALT, MODE_HORIZ, MODE_VERT};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use swayipc::{Connection, Node, NodeLayout, Rect, WindowChange};
+
+/// Temporary mark used as a `move container to mark` target.
+const MARK: &str = "_i3h_tile";
+
+/// Where tiling commands go: i3 in the daemon, a recorder in tests.
+pub trait Commands {
+    fn run(&mut self, cmd: &str) -> Result<()>;
+}
+
+impl Commands for Connection {
+    fn run(&mut self, cmd: &str) -> Result<()> {
+        self.run_command(cmd)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Split {
+    H,
+    V,
+}
+
+impl Split {
+    #[inline]
+    fn as_str(self) -> &'static str {
+        match self {
+            Split::H => "h",
+            Split::V => "v",
+        }
+    }
+
+    #[inline]
+    fn layout(self) -> NodeLayout {
+        match self {
+            Split::H => NodeLayout::SplitH,
+            Split::V => NodeLayout::SplitV,
+        }
+    }
+
+    /// Size of each half of `r` after splitting it this way.
+    fn halve(self, r: Rect) -> Rect {
+        let mut half = r;
+        match self {
+            Split::H => half.width /= 2,
+            Split::V => half.height /= 2,
+        }
+        half
+    }
+}
+
+/// Split a container of size `r` gets in `mode`; `None` for modes that
+/// place windows themselves (master, spiral).
+fn decide(mode: u8, r: &Rect) -> Option<Split> {
+    match mode {
+        // Perpendicular to the longest dimension
+        MODE_ALT if r.height > r.width => Some(Split::V),
+        MODE_ALT => Some(Split::H),
+        MODE_VERT => Some(Split::V),
+        MODE_HORIZ => Some(Split::H),
+        _ => None,
+    }
+}
+
+/// Find the tiling parent of a window by searching ONLY through `nodes`.
+///
+/// Deliberately excludes `floating_nodes`. When a floating window
+/// (scratchpad, dialog, etc.) is created, this returns `None`, which
+/// causes `on_new` to skip — no special-casing needed.
+pub fn find_tiling_parent(root: &Node, target_id: i64) -> Option<&Node> {
+    for child in &root.nodes {
+        if child.id == target_id {
+            return Some(root);
+        }
+        if let Some(found) = find_tiling_parent(child, target_id) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Smallest rect covering both `a` and `b`.
+fn union(a: &Rect, b: &Rect) -> Rect {
+    let mut r = *a;
+    r.x = a.x.min(b.x);
+    r.y = a.y.min(b.y);
+    r.width = (a.x + a.width).max(b.x + b.width) - r.x;
+    r.height = (a.y + a.height).max(b.y + b.height) - r.y;
+    r
+}
+
+#[derive(Default)]
+pub struct Tiler {
+    /// Container that had focus when the last new window appeared.
+    focused: Option<i64>,
+    /// Last known size of each container, from focus events or `on_new`.
+    rects: HashMap<i64, Rect>,
+    /// Windows whose size `on_new` just computed; their focus event was
+    /// queued before our fix-up and carries the stale pre-split rect.
+    pinned: HashSet<i64>,
+}
+
+impl Tiler {
+    /// Cache side of a window event, before the daemon fetches the tree.
+    /// Issues no commands; `new` is handled by `on_new` once the tree is in.
+    pub fn observe(&mut self, change: WindowChange, con: &Node) {
+        match change {
+            WindowChange::Focus => self.on_focus(con),
+            WindowChange::Close => self.on_close(con.id),
+            _ => {}
+        }
+    }
+
+    /// Record the focused container. Issues no commands.
+    pub fn on_focus(&mut self, con: &Node) {
+        self.focused = Some(con.id);
+        if !self.pinned.remove(&con.id) {
+            self.rects.insert(con.id, con.rect);
+        }
+    }
+
+    /// Container focused before the window being handled appeared: i3
+    /// sends a window's `new` event ahead of its `focus` event.
+    #[inline]
+    pub fn focused(&self) -> Option<i64> {
+        self.focused
+    }
+
+    pub fn on_close(&mut self, id: i64) {
+        self.rects.remove(&id);
+        self.pinned.remove(&id);
+        if self.focused == Some(id) {
+            self.focused = None;
+        }
+    }
+
+    /// Fix up where i3 attached newly created window `new_id` according to
+    /// `mode` (the mode of its workspace).
+    pub fn on_new(&mut self, cmd: &mut impl Commands, tree: &Node, new_id: i64, mode: u8) -> Result<()> {
+        let parent = match find_tiling_parent(tree, new_id) {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        // Skip tabbed/stacked — user chose that layout deliberately
+        if matches!(parent.layout, NodeLayout::Tabbed | NodeLayout::Stacked) {
+            return Ok(());
+        }
+
+        // i3 attaches a new window right after the focused container; fall
+        // back to its neighbour when focus was elsewhere (assign rules,
+        // `move` criteria) or the daemon has not seen a focus event yet.
+        let pos = match parent.nodes.iter().position(|c| c.id == new_id) {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let anchor = self
+            .focused
+            .and_then(|id| parent.nodes.iter().find(|c| c.id == id && c.id != new_id))
+            .or_else(|| pos.checked_sub(1).map(|i| &parent.nodes[i]))
+            .or_else(|| parent.nodes.get(pos + 1));
+        let anchor = match anchor {
+            Some(a) => a,
+            None => return Ok(()), // only window: nothing was split
+        };
+        let new = &parent.nodes[pos];
+
+        let before = self
+            .rects
+            .get(&anchor.id)
+            .copied()
+            .unwrap_or_else(|| union(&anchor.rect, &new.rect));
+        let split = match decide(mode, &before) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        let half = split.halve(before);
+        self.rects.insert(anchor.id, half);
+        self.rects.insert(new_id, half);
+        self.pinned.insert(new_id);
+
+        if parent.layout == split.layout() {
+            return Ok(());
+        }
+        let a = anchor.id;
+        if parent.nodes.len() == 2 {
+            // The pair is the whole container: flip it (`layout` on a child
+            // changes its parent).
+            cmd.run(&format!("[con_id={new_id}] layout split{}", split.as_str()))
+        } else {
+            cmd.run(&format!(
+                "[con_id={a}] split {s}; [con_id={a}] mark --add {MARK}; \
+                 [con_id={new_id}] move container to mark {MARK}; unmark {MARK}",
+                s = split.as_str(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{leaf, node, split, tree, workspace};
+    use serde_json::json;
+    use swayipc::WindowEvent;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Commands for Recorder {
+        fn run(&mut self, cmd: &str) -> Result<()> {
+            self.0.push(cmd.to_string());
+            Ok(())
+        }
+    }
+
+    /// Focus event payload for a window of the given size.
+    fn focus(t: &mut Tiler, id: i64, w: i32, h: i32) {
+        t.on_focus(&node(leaf(id, 0, 0, w, h)));
+    }
+
+    /// Replay recorded `(change, container, tree after the event)` triples
+    /// the way the daemon loop dispatches them: `observe` on every event,
+    /// then `handle_tiling` against the fetched tree for `new`.
+    fn replay(events: &[(&str, (i64, i32, i32), &Node)]) -> Recorder {
+        let mut t = Tiler::default();
+        let mut rec = Recorder::default();
+        for &(change, (id, w, h), tree) in events {
+            let ev: WindowEvent =
+                serde_json::from_value(json!({ "change": change, "container": leaf(id, 0, 0, w, h) }))
+                    .unwrap();
+            t.observe(ev.change, &ev.container);
+            if ev.change == WindowChange::New {
+                crate::handle_tiling(&mut rec, &mut t, tree, ev.container.id).unwrap();
+            }
+        }
+        rec
+    }
+
+    #[test]
+    fn focus_changes_issue_no_commands() {
+        let one = tree(workspace("splith", vec![leaf(1, 0, 0, 800, 600)]));
+        let pair = |b| tree(workspace("splith", vec![leaf(1, 0, 0, 400, 600), leaf(b, 400, 0, 400, 600)]));
+        let (two, three) = (pair(2), pair(3));
+        let four = tree(workspace(
+            "splith",
+            vec![leaf(1, 0, 0, 266, 600), leaf(3, 266, 0, 266, 600), leaf(4, 532, 0, 268, 600)],
+        ));
+        let rec = replay(&[
+            ("focus", (1, 800, 600), &one),
+            // 2 opens beside 1: the workspace already splits h
+            ("new", (2, 400, 600), &two),
+            ("focus", (2, 800, 600), &two),
+            // Focus churn and a move cost nothing
+            ("focus", (1, 400, 600), &two),
+            ("focus", (2, 400, 600), &two),
+            ("move", (2, 400, 600), &two),
+            ("close", (2, 400, 600), &one),
+            ("focus", (1, 800, 600), &one),
+            ("new", (3, 400, 600), &three),
+            ("focus", (3, 800, 600), &three),
+            // 4 opens next to 3, which is tall: the only fix-up
+            ("new", (4, 268, 600), &four),
+        ]);
+        assert_eq!(rec.0.len(), 1, "{:?}", rec.0);
+        assert!(rec.0[0].starts_with("[con_id=3] split v"), "{:?}", rec.0);
+    }
+
+    #[test]
+    fn alternating_sequence() {
+        let mut t = Tiler::default();
+        let mut rec = Recorder::default();
+
+        // 1 alone on an 800×600 workspace
+        focus(&mut t, 1, 800, 600);
+        // 2 opens: wide → split h, which the workspace already is
+        let tree = workspace(
+            "splith",
+            vec![leaf(1, 0, 0, 400, 600), leaf(2, 400, 0, 400, 600)],
+        );
+        t.on_new(&mut rec, &tree, 2, MODE_ALT).unwrap();
+        // i3's focus event for 2 still has the pre-split rect
+        focus(&mut t, 2, 800, 600);
+        assert_eq!(rec.0.len(), 0);
+
+        // 3 opens next to 2: 2 is tall → wrap it in a vertical split
+        let tree = workspace(
+            "splith",
+            vec![leaf(1, 0, 0, 266, 600), leaf(2, 266, 0, 266, 600), leaf(3, 532, 0, 268, 600)],
+        );
+        t.on_new(&mut rec, &tree, 3, MODE_ALT).unwrap();
+        focus(&mut t, 3, 268, 600);
+        assert_eq!(
+            rec.0,
+            ["[con_id=2] split v; [con_id=2] mark --add _i3h_tile; \
+              [con_id=3] move container to mark _i3h_tile; unmark _i3h_tile"]
+        );
+
+        // 4 opens below 3 in the new splitv: 3 is 400×300 → split h
+        let tree = workspace(
+            "splith",
+            vec![
+                leaf(1, 0, 0, 400, 600),
+                split("splitv", vec![leaf(2, 400, 0, 400, 300), leaf(3, 400, 300, 200, 300), leaf(4, 600, 300, 200, 300)]),
+            ],
+        );
+        t.on_new(&mut rec, &tree, 4, MODE_ALT).unwrap();
+        assert_eq!(rec.0.len(), 2);
+        assert!(rec.0[1].starts_with("[con_id=3] split h"));
+    }
+
+    #[test]
+    fn fixed_modes_flip_pairs_and_skip_floating() {
+        let mut t = Tiler::default();
+        let mut rec = Recorder::default();
+        focus(&mut t, 1, 800, 600);
+
+        let tree = workspace(
+            "splith",
+            vec![leaf(1, 0, 0, 400, 600), leaf(2, 400, 0, 400, 600)],
+        );
+        t.on_new(&mut rec, &tree, 2, MODE_VERT).unwrap();
+        assert_eq!(rec.0, ["[con_id=2] layout splitv"]);
+
+        // Same layout, horizontal mode: already right
+        t.on_new(&mut rec, &tree, 2, MODE_HORIZ).unwrap();
+        // Master/spiral place windows themselves
+        t.on_new(&mut rec, &tree, 2, crate::MODE_MASTER).unwrap();
+        // A floating window (not in `nodes`) is ignored
+        t.on_new(&mut rec, &tree, 99, MODE_VERT).unwrap();
+        assert_eq!(rec.0.len(), 1);
+    }
+}
@@ -0,0 +1,102 @@
+//! i3 / Sway detection and IPC socket discovery.
+//!
+//! Both speak the same IPC protocol; the differences that matter here are
+//! how the socket is found and that native Wayland windows carry an
+//! `app_id` instead of X11 `window_properties`.
+
+use anyhow::{bail, Result};
+use std::env;
+use std::os::unix::net::UnixStream;
+use std::process::Command as Cmd;
+use std::sync::{OnceLock, RwLock};
+use swayipc::Connection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wm {
+    I3,
+    Sway,
+}
+
+impl Wm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Wm::I3 => "i3",
+            Wm::Sway => "sway",
+        }
+    }
+}
+
+static WM: OnceLock<Wm> = OnceLock::new();
+
+/// The window manager detected at startup (i3 until `detect` has run).
+#[inline]
+pub fn get() -> Wm {
+    WM.get().copied().unwrap_or(Wm::I3)
+}
+
+/// Ask the connected WM for its version: Sway reports 1.x, i3 4.x.
+pub fn detect(conn: &mut Connection) -> Wm {
+    let wm = match conn.get_version() {
+        Ok(v) if v.major < 4 => Wm::Sway,
+        Ok(_) => Wm::I3,
+        // Fall back to the session type
+        Err(_) if env::var_os("SWAYSOCK").is_some() => Wm::Sway,
+        Err(_) => Wm::I3,
+    };
+    let _ = WM.set(wm);
+    wm
+}
+
+fn get_socketpath(bin: &str) -> Option<String> {
+    let output = Cmd::new(bin).arg("--get-socketpath").output().ok()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !path.is_empty()).then_some(path)
+}
+
+/// Socket path the last successful `connect` used.
+static SOCKET: RwLock<Option<String>> = RwLock::new(None);
+
+/// Candidate socket paths, best first. `I3SOCK`/`SWAYSOCK` win at startup;
+/// once a path has gone dead (i3 restarted with a new pid, new session) the
+/// inherited variables are stale too, so the WM binaries are asked first —
+/// Sway first inside a Wayland session, where a stray i3 install would only
+/// report a dead X11 socket.
+fn candidates(stale: Option<&str>) -> impl Iterator<Item = String> + '_ {
+    let from_env = ["I3SOCK", "SWAYSOCK"].into_iter().filter_map(|v| env::var(v).ok());
+    let bins = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        ["sway", "i3"]
+    } else {
+        ["i3", "sway"]
+    };
+    // Lazy: the binaries are only spawned if the variables don't work
+    let probed = bins.into_iter().filter_map(get_socketpath);
+
+    let ordered: Box<dyn Iterator<Item = String>> = match stale {
+        None => Box::new(from_env.chain(probed)),
+        Some(_) => Box::new(probed.chain(from_env)),
+    };
+    ordered.filter(move |p| Some(p.as_str()) != stale)
+}
+
+/// Open an IPC connection, rediscovering the socket if the cached one is dead.
+pub fn connect() -> Result<Connection> {
+    let cached = SOCKET.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(path) = &cached {
+        if let Ok(stream) = UnixStream::connect(path) {
+            return Ok(stream.into());
+        }
+    }
+    for path in candidates(cached.as_deref()) {
+        if let Ok(stream) = UnixStream::connect(&path) {
+            *SOCKET.write().unwrap_or_else(|e| e.into_inner()) = Some(path);
+            return Ok(stream.into());
+        }
+    }
+    bail!("no i3/sway IPC socket reachable")
+}
+
+/// Socket path of the last connection. i3 and Sway embed their pid in it, so
+/// it also identifies the WM session.
+pub fn socket() -> Option<String> {
+    SOCKET.read().unwrap_or_else(|e| e.into_inner()).clone()
+}